@@ -0,0 +1,182 @@
+use chrono::{TimeZone, Utc};
+use serenity::model::id::{ChannelId, UserId};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+use crate::store::MessageContent;
+
+/// Most users and channels listed
+const TOP_GROUPS: usize = 25;
+
+/// Common short names of languages, so `rs` and `rust` blocks count as one language
+const LANGUAGE_ALIASES: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("py", "python"),
+    ("js", "javascript"),
+    ("jsx", "javascript"),
+    ("ts", "typescript"),
+    ("tsx", "typescript"),
+    ("sh", "shell"),
+    ("bash", "shell"),
+    ("zsh", "shell"),
+    ("console", "shell"),
+    ("c++", "cpp"),
+    ("cxx", "cpp"),
+    ("cs", "csharp"),
+    ("c#", "csharp"),
+    ("kt", "kotlin"),
+    ("rb", "ruby"),
+    ("yml", "yaml"),
+    ("md", "markdown"),
+    ("golang", "go"),
+];
+
+/// A fenced code block in a message
+#[derive(Debug, PartialEq, Eq)]
+pub struct CodeBlock {
+    /// The declared language, lowercase and with aliases resolved
+    pub language: Option<String>,
+    pub lines: usize,
+}
+
+/// Number of code blocks and the lines in them
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CodeCount {
+    pub blocks: usize,
+    pub lines: usize,
+}
+
+impl CodeCount {
+    fn add(&mut self, block: &CodeBlock) {
+        self.blocks += 1;
+        self.lines += block.lines;
+    }
+}
+
+/// Code shared in a set of messages, built by feeding messages to `add`
+#[derive(Default)]
+pub struct CodeAnalysis {
+    total: CodeCount,
+    languages: HashMap<Option<String>, CodeCount>,
+    users: HashMap<UserId, CodeCount>,
+    channels: HashMap<ChannelId, CodeCount>,
+    /// Code per month, as `YYYY-MM`
+    months: BTreeMap<String, CodeCount>,
+}
+
+impl CodeAnalysis {
+    pub fn new() -> CodeAnalysis {
+        CodeAnalysis::default()
+    }
+
+    pub fn add(&mut self, msg: &MessageContent) {
+        let blocks = extract(&msg.content);
+        if blocks.is_empty() {
+            return;
+        }
+
+        let month = Utc.timestamp(msg.time, 0).format("%Y-%m").to_string();
+        for block in blocks {
+            self.total.add(&block);
+            self.users.entry(msg.author_id).or_default().add(&block);
+            self.channels.entry(msg.channel_id).or_default().add(&block);
+            self.months.entry(month.clone()).or_default().add(&block);
+            self.languages
+                .entry(block.language.clone())
+                .or_default()
+                .add(&block);
+        }
+    }
+
+    pub fn total(&self) -> CodeCount {
+        self.total
+    }
+
+    /// Languages by lines of code, blocks without a language are listed as `None`
+    pub fn languages(&self) -> Vec<(Option<String>, CodeCount)> {
+        let mut languages = self
+            .languages
+            .iter()
+            .map(|(language, &count)| (language.clone(), count))
+            .collect::<Vec<_>>();
+        languages.sort_by(|a, b| b.1.lines.cmp(&a.1.lines).then_with(|| a.0.cmp(&b.0)));
+        languages
+    }
+
+    /// Users who shared the most lines of code
+    pub fn users(&self) -> Vec<(UserId, CodeCount)> {
+        top_groups(&self.users)
+    }
+
+    /// Channels with the most lines of code
+    pub fn channels(&self) -> Vec<(ChannelId, CodeCount)> {
+        top_groups(&self.channels)
+    }
+
+    /// Code shared per month, oldest first
+    pub fn months(&self) -> Vec<(String, CodeCount)> {
+        self.months
+            .iter()
+            .map(|(month, &count)| (month.clone(), count))
+            .collect()
+    }
+}
+
+/// Finds the code blocks fenced by triple backticks in message content
+///
+/// A word right after the opening backticks followed by a line break is the block's
+/// language, as Discord highlights it. Blocks that are never closed are not code.
+pub fn extract(content: &str) -> Vec<CodeBlock> {
+    let parts = content.split("```").collect::<Vec<_>>();
+    // With an even number of parts the last fence is not closed
+    let closed = if parts.len() % 2 == 0 {
+        parts.len() - 1
+    } else {
+        parts.len()
+    };
+
+    parts[..closed]
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .map(|&block| {
+            let (language, code) = match block.find('\n') {
+                Some(newline) if is_language(&block[..newline]) => (
+                    Some(language_name(&block[..newline])),
+                    &block[newline + 1..],
+                ),
+                _ => (None, block),
+            };
+            CodeBlock {
+                language,
+                lines: code.trim_matches('\n').lines().count(),
+            }
+        })
+        .filter(|block| block.lines > 0)
+        .collect()
+}
+
+fn is_language(word: &str) -> bool {
+    !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_alphanumeric() || "+#-_.".contains(c))
+}
+
+fn language_name(word: &str) -> String {
+    let word = word.to_lowercase();
+    LANGUAGE_ALIASES
+        .iter()
+        .find(|&&(alias, _)| alias == word)
+        .map_or(word, |&(_, language)| language.to_owned())
+}
+
+fn top_groups<T: Copy + Ord + Hash>(groups: &HashMap<T, CodeCount>) -> Vec<(T, CodeCount)> {
+    let mut groups = groups
+        .iter()
+        .map(|(&id, &count)| (id, count))
+        .collect::<Vec<_>>();
+    groups.sort_by_key(|&(id, count)| (std::cmp::Reverse(count.lines), id));
+    groups.truncate(TOP_GROUPS);
+    groups
+}
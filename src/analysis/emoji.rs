@@ -0,0 +1,310 @@
+use chrono::{TimeZone, Utc};
+use serenity::model::id::{EmojiId, GuildId, UserId};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+use crate::store::MessageContent;
+
+/// Most emoji listed overall and for each user or guild
+pub const TOP_EMOJI: usize = 50;
+/// Most users or guilds listed
+const TOP_GROUPS: usize = 25;
+/// Emoji listed as favourites of each user or guild
+const TOP_GROUP_EMOJI: usize = 5;
+
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+const VARIATION_SELECTOR: char = '\u{FE0F}';
+const KEYCAP: char = '\u{20E3}';
+
+/// An emoji used in a message
+#[derive(Clone, Debug)]
+pub enum Emoji {
+    Unicode(String),
+    /// A guild emoji, written as `<:name:id>` or `<a:name:id>` in the content
+    Custom {
+        id: EmojiId,
+        name: String,
+        animated: bool,
+    },
+}
+
+impl Emoji {
+    /// Identifies the emoji, custom emoji keep their id when renamed
+    fn key(&self) -> String {
+        match self {
+            Emoji::Unicode(text) => text.clone(),
+            Emoji::Custom { id, .. } => id.0.to_string(),
+        }
+    }
+
+    /// The unicode emoji or the name of a custom emoji
+    pub fn name(&self) -> &str {
+        match self {
+            Emoji::Unicode(text) => text,
+            Emoji::Custom { name, .. } => name,
+        }
+    }
+
+    /// Where Discord serves the image of a custom emoji
+    pub fn image_url(&self) -> Option<String> {
+        match self {
+            Emoji::Unicode(_) => None,
+            Emoji::Custom { id, animated, .. } => Some(format!(
+                "https://cdn.discordapp.com/emojis/{}.{}",
+                id.0,
+                if *animated { "gif" } else { "png" }
+            )),
+        }
+    }
+}
+
+/// Emoji counts over a set of messages, built by feeding messages to `add`
+#[derive(Default)]
+pub struct EmojiAnalysis {
+    total: usize,
+    /// The last seen form of every emoji by key, for names of custom emoji
+    emoji: HashMap<String, Emoji>,
+    counts: HashMap<String, usize>,
+    users: HashMap<UserId, HashMap<String, usize>>,
+    guilds: HashMap<GuildId, HashMap<String, usize>>,
+    /// Emoji used per month, as `YYYY-MM`
+    months: BTreeMap<String, usize>,
+}
+
+/// Emoji used by one user or in one guild
+#[derive(Debug)]
+pub struct GroupEmoji<T> {
+    pub id: T,
+    pub count: usize,
+    /// Most used first
+    pub top: Vec<(Emoji, usize)>,
+}
+
+impl EmojiAnalysis {
+    pub fn new() -> EmojiAnalysis {
+        EmojiAnalysis::default()
+    }
+
+    pub fn add(&mut self, msg: &MessageContent) {
+        let found = extract(&msg.content);
+        if found.is_empty() {
+            return;
+        }
+
+        self.total += found.len();
+        let month = Utc.timestamp(msg.time, 0).format("%Y-%m").to_string();
+        *self.months.entry(month).or_insert(0) += found.len();
+
+        for emoji in found {
+            let key = emoji.key();
+            *self.counts.entry(key.clone()).or_insert(0) += 1;
+            *self
+                .users
+                .entry(msg.author_id)
+                .or_default()
+                .entry(key.clone())
+                .or_insert(0) += 1;
+            if let Some(guild_id) = msg.guild_id {
+                *self
+                    .guilds
+                    .entry(guild_id)
+                    .or_default()
+                    .entry(key.clone())
+                    .or_insert(0) += 1;
+            }
+            self.emoji.insert(key, emoji);
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Most used emoji
+    pub fn top(&self, limit: usize) -> Vec<(Emoji, usize)> {
+        self.top_of(&self.counts, limit)
+    }
+
+    /// Users who used the most emoji, with their favourites
+    pub fn users(&self) -> Vec<GroupEmoji<UserId>> {
+        self.groups(&self.users)
+    }
+
+    /// Guilds with the most emoji, with the most used ones there
+    pub fn guilds(&self) -> Vec<GroupEmoji<GuildId>> {
+        self.groups(&self.guilds)
+    }
+
+    /// Emoji used per month, oldest first
+    pub fn months(&self) -> Vec<(String, usize)> {
+        self.months
+            .iter()
+            .map(|(month, &count)| (month.clone(), count))
+            .collect()
+    }
+
+    fn groups<T: Copy + Ord + Hash>(
+        &self,
+        groups: &HashMap<T, HashMap<String, usize>>,
+    ) -> Vec<GroupEmoji<T>> {
+        let mut result = groups
+            .iter()
+            .map(|(&id, counts)| GroupEmoji {
+                id,
+                count: counts.values().sum(),
+                top: self.top_of(counts, TOP_GROUP_EMOJI),
+            })
+            .collect::<Vec<_>>();
+        result.sort_by_key(|group| (std::cmp::Reverse(group.count), group.id));
+        result.truncate(TOP_GROUPS);
+        result
+    }
+
+    fn top_of(&self, counts: &HashMap<String, usize>, limit: usize) -> Vec<(Emoji, usize)> {
+        let mut entries = counts.iter().collect::<Vec<_>>();
+        entries.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        entries
+            .into_iter()
+            .take(limit)
+            .map(|(key, &count)| (self.emoji[key].clone(), count))
+            .collect()
+    }
+}
+
+/// Finds the unicode and custom emoji in message content, in order
+pub fn extract(content: &str) -> Vec<Emoji> {
+    let mut found = Vec::new();
+    let mut rest = content;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '<' {
+            if let Some((emoji, len)) = parse_custom(rest) {
+                found.push(emoji);
+                rest = &rest[len..];
+                continue;
+            }
+        }
+
+        let len = unicode_emoji_len(rest);
+        if len > 0 {
+            found.push(Emoji::Unicode(
+                rest[..len].trim_end_matches(VARIATION_SELECTOR).to_owned(),
+            ));
+            rest = &rest[len..];
+        } else {
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+
+    found
+}
+
+/// Parses `<:name:id>` or `<a:name:id>` at the start of `s`, returning its length
+fn parse_custom(s: &str) -> Option<(Emoji, usize)> {
+    let end = s.find('>')?;
+    let mut parts = s[1..end].split(':');
+    let animated = match parts.next()? {
+        "" => false,
+        "a" => true,
+        _ => return None,
+    };
+    let name = parts.next()?;
+    let id = parts.next()?.parse().ok()?;
+    if name.is_empty() || parts.next().is_some() {
+        return None;
+    }
+
+    let emoji = Emoji::Custom {
+        id: EmojiId(id),
+        name: name.to_owned(),
+        animated,
+    };
+    Some((emoji, end + 1))
+}
+
+/// Length in bytes of the unicode emoji at the start of `s`, 0 if there is none
+///
+/// Skin tones, variation selectors and sequences joined with zero width joiners are part
+/// of the emoji, as is the second half of a flag.
+fn unicode_emoji_len(s: &str) -> usize {
+    let mut chars = s.char_indices().peekable();
+    let first = match chars.next() {
+        Some((_, c)) => c,
+        None => return 0,
+    };
+
+    // Keycaps like 1️⃣ start with an ordinary character
+    if first.is_ascii_digit() || first == '#' || first == '*' {
+        let mut rest = s[first.len_utf8()..].chars();
+        let mut next = rest.next();
+        let mut len = first.len_utf8();
+        if next == Some(VARIATION_SELECTOR) {
+            len += VARIATION_SELECTOR.len_utf8();
+            next = rest.next();
+        }
+        return if next == Some(KEYCAP) {
+            len + KEYCAP.len_utf8()
+        } else {
+            0
+        };
+    }
+
+    if !is_emoji(first) {
+        return 0;
+    }
+    let mut end = first.len_utf8();
+
+    if is_regional_indicator(first) {
+        if let Some(&(i, c)) = chars.peek() {
+            if is_regional_indicator(c) {
+                return i + c.len_utf8();
+            }
+        }
+        return end;
+    }
+
+    while let Some(&(i, c)) = chars.peek() {
+        if c == VARIATION_SELECTOR || is_skin_tone(c) {
+            chars.next();
+            end = i + c.len_utf8();
+        } else if c == ZERO_WIDTH_JOINER {
+            chars.next();
+            match chars.next() {
+                Some((j, joined)) if is_emoji(joined) => end = j + joined.len_utf8(),
+                _ => break,
+            }
+        } else {
+            break;
+        }
+    }
+    end
+}
+
+fn is_emoji(c: char) -> bool {
+    match c as u32 {
+        0x1F000..=0x1F0FF
+        | 0x1F170..=0x1F251
+        | 0x1F300..=0x1F5FF
+        | 0x1F600..=0x1F64F
+        | 0x1F680..=0x1F6FF
+        | 0x1F900..=0x1F9FF
+        | 0x1FA70..=0x1FAFF
+        | 0x2600..=0x27BF
+        | 0x2B50
+        | 0x2B55
+        | 0x2934..=0x2935
+        | 0x3030
+        | 0x303D
+        | 0x3297
+        | 0x3299 => !is_skin_tone(c),
+        _ => is_regional_indicator(c),
+    }
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+fn is_skin_tone(c: char) -> bool {
+    ('\u{1F3FB}'..='\u{1F3FF}').contains(&c)
+}
@@ -0,0 +1,96 @@
+/// Hosts that are the same site as another, so links to them are counted together
+const DOMAIN_ALIASES: &[(&str, &str)] = &[
+    ("youtu.be", "youtube.com"),
+    ("x.com", "twitter.com"),
+    ("t.co", "twitter.com"),
+    ("redd.it", "reddit.com"),
+    ("old.reddit.com", "reddit.com"),
+    ("gist.github.com", "github.com"),
+    ("raw.githubusercontent.com", "github.com"),
+    ("discordapp.com", "discord.com"),
+    ("discord.gg", "discord.com"),
+    ("cdn.discordapp.com", "discord.com"),
+    ("media.discordapp.net", "discord.com"),
+    ("amzn.to", "amazon.com"),
+];
+
+/// Prefixes of hosts that serve the same site as the host without them
+const HOST_PREFIXES: &[&str] = &["www.", "m.", "mobile."];
+
+/// A link shared in a message
+#[derive(Debug, PartialEq, Eq)]
+pub struct Link {
+    pub url: String,
+    /// The site the link points to, with mirrors and short links grouped under one name
+    pub domain: String,
+}
+
+/// Finds the `http` and `https` links in message content
+///
+/// Punctuation at the end of a link is taken as part of the sentence around it, and links
+/// in `<>`, which Discord shows without a preview, end before the `>`.
+pub fn extract(content: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+
+    for word in content.split(|c: char| c.is_whitespace() || c == '<' || c == '>') {
+        let start = match word.find("https://").or_else(|| word.find("http://")) {
+            Some(start) => start,
+            None => continue,
+        };
+        let mut url = &word[start..];
+
+        loop {
+            let trimmed = url.trim_end_matches(|c| ".,;:!?'\"*_~|".contains(c));
+            // Keep closing parentheses that belong to the link, as on Wikipedia
+            let unbalanced = trimmed.matches(')').count() > trimmed.matches('(').count();
+            let trimmed = if trimmed.ends_with(')') && unbalanced {
+                &trimmed[..trimmed.len() - 1]
+            } else {
+                trimmed
+            };
+            if trimmed == url {
+                break;
+            }
+            url = trimmed;
+        }
+
+        if let Some(domain) = domain(url) {
+            links.push(Link {
+                url: url.to_owned(),
+                domain,
+            });
+        }
+    }
+
+    links
+}
+
+/// The grouped domain of an `http` or `https` url, `None` if it has no host
+fn domain(url: &str) -> Option<String> {
+    let rest = url.splitn(2, "://").nth(1)?;
+    let host = rest
+        .split(|c| c == '/' || c == '?' || c == '#')
+        .next()?
+        // Credentials and port
+        .rsplit('@')
+        .next()?
+        .split(':')
+        .next()?
+        .to_lowercase();
+
+    let mut host = host.trim_end_matches('.');
+    for prefix in HOST_PREFIXES {
+        if host.starts_with(prefix) {
+            host = &host[prefix.len()..];
+        }
+    }
+    if host.is_empty() || !host.contains('.') {
+        return None;
+    }
+
+    let domain = DOMAIN_ALIASES
+        .iter()
+        .find(|&&(alias, _)| alias == host)
+        .map_or(host, |&(_, domain)| domain);
+    Some(domain.to_owned())
+}
@@ -0,0 +1,19 @@
+pub mod code;
+pub mod emoji;
+pub mod links;
+pub mod sessions;
+pub mod words;
+
+/// The middle of the values, or the mean of the two middle ones, `None` if there are none
+pub fn median(values: &mut [i64]) -> Option<i64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort();
+    let middle = values.len() / 2;
+    Some(if values.len() % 2 == 0 {
+        (values[middle - 1] + values[middle]) / 2
+    } else {
+        values[middle]
+    })
+}
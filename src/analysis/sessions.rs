@@ -0,0 +1,133 @@
+use serenity::model::id::{ChannelId, UserId};
+use std::collections::{HashMap, HashSet};
+
+use super::median;
+use crate::store::TimelineMessage;
+
+/// Seconds of silence in a channel after which the next message starts a new session
+pub const DEFAULT_SESSION_GAP: i64 = 30 * 60;
+
+/// Messages in one channel without a pause longer than the session gap
+#[derive(Debug)]
+pub struct Session {
+    pub channel_id: ChannelId,
+    /// Author of the first message
+    pub starter: UserId,
+    pub start: i64,
+    pub end: i64,
+    pub messages: usize,
+    pub participants: usize,
+}
+
+impl Session {
+    pub fn length(&self) -> i64 {
+        self.end - self.start
+    }
+}
+
+/// Totals over a list of sessions
+#[derive(Debug, Default)]
+pub struct SessionSummary {
+    pub sessions: usize,
+    pub median_length: i64,
+    pub mean_length: f64,
+    pub longest: i64,
+    pub mean_messages: f64,
+    /// Users by the number of sessions they started, most first
+    pub starters: Vec<(UserId, usize)>,
+}
+
+/// How quickly a user answers messages of others
+#[derive(Debug)]
+pub struct ResponseTime {
+    pub user_id: UserId,
+    pub responses: usize,
+    pub median: i64,
+}
+
+/// Splits the timeline into sessions, it has to be ordered by channel and time
+pub fn sessions(timeline: &[TimelineMessage], gap: i64) -> Vec<Session> {
+    let mut sessions = Vec::new();
+    let mut participants = HashSet::new();
+
+    for (i, msg) in timeline.iter().enumerate() {
+        let continues = i > 0 && {
+            let prev = &timeline[i - 1];
+            prev.channel_id == msg.channel_id && msg.time - prev.time <= gap
+        };
+
+        if continues {
+            let session: &mut Session = sessions.last_mut().expect("a session was started");
+            session.end = msg.time;
+            session.messages += 1;
+            if participants.insert(msg.author_id) {
+                session.participants += 1;
+            }
+        } else {
+            participants.clear();
+            participants.insert(msg.author_id);
+            sessions.push(Session {
+                channel_id: msg.channel_id,
+                starter: msg.author_id,
+                start: msg.time,
+                end: msg.time,
+                messages: 1,
+                participants: 1,
+            });
+        }
+    }
+
+    sessions
+}
+
+pub fn summarize(sessions: &[Session]) -> SessionSummary {
+    if sessions.is_empty() {
+        return SessionSummary::default();
+    }
+
+    let mut lengths = sessions.iter().map(Session::length).collect::<Vec<_>>();
+    let total_messages: usize = sessions.iter().map(|session| session.messages).sum();
+
+    let mut starters = HashMap::new();
+    for session in sessions {
+        *starters.entry(session.starter).or_insert(0) += 1;
+    }
+    let mut starters = starters.into_iter().collect::<Vec<_>>();
+    starters.sort_by_key(|&(user_id, count)| (std::cmp::Reverse(count), user_id));
+
+    SessionSummary {
+        sessions: sessions.len(),
+        mean_length: lengths.iter().sum::<i64>() as f64 / sessions.len() as f64,
+        longest: lengths.iter().cloned().max().unwrap_or(0),
+        median_length: median(&mut lengths).unwrap_or(0),
+        mean_messages: total_messages as f64 / sessions.len() as f64,
+        starters,
+    }
+}
+
+/// Median time each user took to answer, most active responders first
+///
+/// A message answers the one before it if that was sent by someone else in the same
+/// channel and session, so answers after a longer pause than `gap` are not counted.
+pub fn response_times(timeline: &[TimelineMessage], gap: i64) -> Vec<ResponseTime> {
+    let mut delays: HashMap<UserId, Vec<i64>> = HashMap::new();
+
+    for pair in timeline.windows(2) {
+        let (prev, msg) = (&pair[0], &pair[1]);
+        let delay = msg.time - prev.time;
+        if prev.channel_id == msg.channel_id && prev.author_id != msg.author_id && delay <= gap {
+            delays.entry(msg.author_id).or_default().push(delay);
+        }
+    }
+
+    let mut times = delays
+        .into_iter()
+        .map(|(user_id, mut delays)| ResponseTime {
+            user_id,
+            responses: delays.len(),
+            median: median(&mut delays).unwrap_or(0),
+        })
+        .collect::<Vec<_>>();
+    times.sort_by_key(|time| (std::cmp::Reverse(time.responses), time.user_id));
+    times
+}
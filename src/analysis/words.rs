@@ -0,0 +1,243 @@
+use chrono::{TimeZone, Utc};
+use serenity::model::id::ChannelId;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::store::MessageContent;
+
+/// Most words and bigrams listed
+pub const TOP_WORDS: usize = 100;
+/// Most words listed as characteristic for a channel
+const TOP_CHANNEL_WORDS: usize = 10;
+/// Words used fewer times in a channel are not considered characteristic for it
+const MIN_CHANNEL_WORD_COUNT: usize = 3;
+
+/// Common English words left out unless the configuration replaces the list
+pub const DEFAULT_STOPWORDS: &[&str] = &[
+    "a", "about", "after", "all", "also", "am", "an", "and", "any", "are", "as", "at", "be",
+    "because", "been", "but", "by", "can", "could", "did", "do", "does", "don't", "for", "from",
+    "get", "got", "had", "has", "have", "he", "her", "him", "his", "how", "i", "i'm", "if", "in",
+    "into", "is", "it", "it's", "its", "just", "like", "me", "more", "my", "no", "not", "now",
+    "of", "on", "one", "only", "or", "other", "our", "out", "she", "so", "some", "than", "that",
+    "that's", "the", "their", "them", "then", "there", "they", "this", "to", "too", "up", "us",
+    "was", "we", "were", "what", "when", "which", "who", "will", "with", "would", "you", "your",
+];
+
+/// Word statistics over a set of messages, built by feeding messages to `add`
+pub struct WordAnalysis {
+    stopwords: HashSet<String>,
+    messages: usize,
+    words: usize,
+    characters: usize,
+    word_counts: HashMap<String, usize>,
+    bigram_counts: HashMap<(String, String), usize>,
+    /// Distinct words per month, as `YYYY-MM`
+    months: BTreeMap<String, HashSet<String>>,
+    channel_counts: HashMap<ChannelId, HashMap<String, usize>>,
+}
+
+/// Distinct words used in a month
+#[derive(Debug)]
+pub struct Vocabulary {
+    pub month: String,
+    pub distinct: usize,
+    /// Words not used in any earlier month
+    pub new: usize,
+    /// Distinct words used up to and including this month
+    pub cumulative: usize,
+}
+
+/// Words used more in one channel than in the others, scored by TF-IDF
+#[derive(Debug)]
+pub struct ChannelWords {
+    pub channel_id: ChannelId,
+    pub words: Vec<(String, f64)>,
+}
+
+impl WordAnalysis {
+    pub fn new(stopwords: &Option<Vec<String>>) -> WordAnalysis {
+        let stopwords = match stopwords {
+            Some(words) => words.iter().map(|word| word.to_lowercase()).collect(),
+            None => DEFAULT_STOPWORDS
+                .iter()
+                .map(|&word| word.to_owned())
+                .collect(),
+        };
+
+        WordAnalysis {
+            stopwords,
+            messages: 0,
+            words: 0,
+            characters: 0,
+            word_counts: HashMap::new(),
+            bigram_counts: HashMap::new(),
+            months: BTreeMap::new(),
+            channel_counts: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, msg: &MessageContent) {
+        let tokens = tokenize(&msg.content);
+        self.messages += 1;
+        self.words += tokens.len();
+        self.characters += msg.content.chars().count();
+
+        let month = Utc.timestamp(msg.time, 0).format("%Y-%m").to_string();
+        let month = self.months.entry(month).or_insert_with(HashSet::new);
+        let channel = self
+            .channel_counts
+            .entry(msg.channel_id)
+            .or_insert_with(HashMap::new);
+
+        let mut previous: Option<&String> = None;
+        for token in &tokens {
+            if self.stopwords.contains(token) {
+                // Bigrams across a stopword would pair words that were not next to each other
+                previous = None;
+                continue;
+            }
+
+            *self.word_counts.entry(token.clone()).or_insert(0) += 1;
+            *channel.entry(token.clone()).or_insert(0) += 1;
+            if !month.contains(token) {
+                month.insert(token.clone());
+            }
+            if let Some(previous) = previous {
+                *self
+                    .bigram_counts
+                    .entry((previous.clone(), token.clone()))
+                    .or_insert(0) += 1;
+            }
+            previous = Some(token);
+        }
+    }
+
+    pub fn messages(&self) -> usize {
+        self.messages
+    }
+
+    pub fn average_words(&self) -> f64 {
+        average(self.words, self.messages)
+    }
+
+    pub fn average_characters(&self) -> f64 {
+        average(self.characters, self.messages)
+    }
+
+    /// Most used words, leaving out stopwords
+    pub fn top_words(&self, limit: usize) -> Vec<(String, usize)> {
+        top(
+            self.word_counts
+                .iter()
+                .map(|(word, &count)| (word.clone(), count)),
+            limit,
+        )
+    }
+
+    /// Most used pairs of adjacent words, joined by a space
+    pub fn top_bigrams(&self, limit: usize) -> Vec<(String, usize)> {
+        top(
+            self.bigram_counts
+                .iter()
+                .map(|((first, second), &count)| (format!("{} {}", first, second), count)),
+            limit,
+        )
+    }
+
+    /// Vocabulary size per month, oldest first
+    pub fn vocabulary(&self) -> Vec<Vocabulary> {
+        let mut seen = HashSet::new();
+        self.months
+            .iter()
+            .map(|(month, words)| {
+                let new = words.iter().filter(|&word| seen.insert(word)).count();
+                Vocabulary {
+                    month: month.clone(),
+                    distinct: words.len(),
+                    new,
+                    cumulative: seen.len(),
+                }
+            })
+            .collect()
+    }
+
+    /// Words characteristic for each channel
+    ///
+    /// A word scores high when it is frequent in the channel and used in few other channels.
+    /// Words used in every channel score zero, so with a single channel none are listed.
+    pub fn channel_words(&self) -> Vec<ChannelWords> {
+        let channels = self.channel_counts.len() as f64;
+        let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+        for counts in self.channel_counts.values() {
+            for word in counts.keys() {
+                *document_frequency.entry(word).or_insert(0) += 1;
+            }
+        }
+
+        let mut result = self
+            .channel_counts
+            .iter()
+            .map(|(&channel_id, counts)| {
+                let total = counts.values().sum::<usize>() as f64;
+                let mut words = counts
+                    .iter()
+                    .filter(|&(_, &count)| count >= MIN_CHANNEL_WORD_COUNT)
+                    .map(|(word, &count)| {
+                        let idf = (channels / document_frequency[word.as_str()] as f64).ln();
+                        (word.clone(), count as f64 / total * idf)
+                    })
+                    .filter(|&(_, score)| score > 0.0)
+                    .collect::<Vec<_>>();
+                words.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+                words.truncate(TOP_CHANNEL_WORDS);
+                ChannelWords { channel_id, words }
+            })
+            .collect::<Vec<_>>();
+        result.sort_by_key(|channel| channel.channel_id);
+        result
+    }
+}
+
+/// Splits message content into lowercase words
+///
+/// Links, mentions, custom emoji and code blocks are skipped, as are numbers and single
+/// characters. Apostrophes inside words are kept so contractions stay one word.
+pub fn tokenize(content: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+
+    for (i, part) in content.split("```").enumerate() {
+        // Odd parts are inside code blocks
+        if i % 2 == 1 {
+            continue;
+        }
+        for chunk in part.split_whitespace() {
+            if chunk.contains("://") || (chunk.starts_with('<') && chunk.ends_with('>')) {
+                continue;
+            }
+            for word in chunk.split(|c: char| !(c.is_alphanumeric() || c == '\'')) {
+                let word = word.trim_matches('\'');
+                if word.chars().count() < 2 || word.chars().all(|c| c.is_numeric()) {
+                    continue;
+                }
+                tokens.push(word.to_lowercase());
+            }
+        }
+    }
+
+    tokens
+}
+
+fn average(total: usize, count: usize) -> f64 {
+    if count == 0 {
+        0.0
+    } else {
+        total as f64 / count as f64
+    }
+}
+
+/// The entries with the highest counts, ties in alphabetical order
+fn top<I: Iterator<Item = (String, usize)>>(entries: I, limit: usize) -> Vec<(String, usize)> {
+    let mut entries = entries.collect::<Vec<_>>();
+    entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    entries.truncate(limit);
+    entries
+}
@@ -1,106 +1,992 @@
+use iron::headers::{CacheControl, CacheDirective};
 use iron::prelude::*;
+use iron::response::WriteBody;
 use iron::status;
 use iron::typemap::Key;
+use log::error;
 use persistent::Read;
+use rusqlite::ErrorCode;
+use serde::Serialize;
+use serde_derive::Serialize;
+
+use serenity::model::id::{ChannelId, GuildId};
+use std::io::{self, Write};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::store::StatsStore;
+use crate::analysis::code::{CodeAnalysis, CodeCount};
+use crate::analysis::emoji::{self, Emoji, EmojiAnalysis};
+use crate::analysis::sessions;
+use crate::analysis::words::{self, WordAnalysis};
+use crate::auth::Scope;
+use crate::config::AnalysisConfig;
+use crate::error::{ApiError, AuthError, StoreError};
+use crate::events::{EventBus, LiveEvent};
+use crate::graph::{self, GraphDocument, GraphFormat};
+use crate::metrics::{self, METRICS};
+use crate::openapi;
+use crate::store::{DailyMessageCount, Filter, StatsStore};
+use crate::time::{self, Bound};
 
 #[cfg(not(debug_assertions))]
 const DASHBOARD_SOURCE: &str = include_str!("../web/src/index.html");
 #[cfg(not(debug_assertions))]
 const DASHBOARD_JS_SOURCE: &str = include_str!("../web/dist/index.js");
 
+/// Most domains and links listed
+const TOP_LINKS: usize = 50;
+
+/// How often a comment is sent on idle event streams to detect closed connections
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
 #[derive(Copy, Clone)]
 pub struct Stats;
 impl Key for Stats {
     type Value = Arc<StatsStore>;
 }
 
-pub fn total_msg_count(req: &mut Request) -> IronResult<Response> {
-    let stats = req.get::<Read<Stats>>().unwrap();
+#[derive(Copy, Clone)]
+pub struct Events;
+impl Key for Events {
+    type Value = Arc<EventBus>;
+}
 
-    Ok(match stats.get_msg_count() {
-        Ok(count) => Response::with((status::Ok, count.to_string())),
-        Err(_) => {
-            eprintln!("Error getting message count");
-            Response::with((status::NoContent, "0".to_owned()))
+#[derive(Copy, Clone)]
+pub struct Analysis;
+impl Key for Analysis {
+    type Value = AnalysisConfig;
+}
+
+/// Builds the filter from the standard query parameters
+///
+/// `guild` and `channel` take comma separated ids, `since` and `until` take
+/// dates or unix timestamps. The result is limited to the guilds the request's
+/// credentials may read.
+fn request_filter(req: &Request) -> Result<Filter, ApiError> {
+    let mut filter = Filter::default();
+
+    for (key, value) in req.url.as_ref().query_pairs() {
+        match &*key {
+            "guild" => filter
+                .guilds
+                .get_or_insert_with(Vec::new)
+                .extend(parse_ids(&value, "guild")?.into_iter().map(GuildId)),
+            "channel" => filter
+                .channels
+                .get_or_insert_with(Vec::new)
+                .extend(parse_ids(&value, "channel")?.into_iter().map(ChannelId)),
+            "since" => filter.since = Some(parse_time_param(&value, "since", Bound::Start)?),
+            "until" => filter.until = Some(parse_time_param(&value, "until", Bound::End)?),
+            _ => {}
         }
-    })
+    }
+
+    if let Some(allowed) = req
+        .extensions
+        .get::<Scope>()
+        .and_then(|scope| scope.guilds.as_ref())
+    {
+        filter.guilds = Some(match filter.guilds {
+            Some(requested) => requested
+                .into_iter()
+                .filter(|guild| allowed.contains(guild))
+                .collect(),
+            None => allowed.clone(),
+        });
+    }
+
+    Ok(filter)
 }
 
-pub fn msg_count(req: &mut Request) -> IronResult<Response> {
-    let stats = req.get::<Read<Stats>>().unwrap();
+/// Rejects requests whose credentials are limited to some guilds
+///
+/// For endpoints about the whole collector and database, which a filter can not narrow down.
+fn require_full_scope(req: &Request) -> Result<(), ApiError> {
+    match req.extensions.get::<Scope>() {
+        Some(scope) if scope.guilds.is_some() => Err(ApiError::Auth(AuthError::Forbidden)),
+        _ => Ok(()),
+    }
+}
+
+fn parse_ids(value: &str, name: &str) -> Result<Vec<u64>, ApiError> {
+    value
+        .split(',')
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            id.trim()
+                .parse()
+                .map_err(|_| ApiError::BadRequest(format!("invalid {} id: {}", name, id)))
+        })
+        .collect()
+}
+
+fn parse_time_param(value: &str, name: &str, bound: Bound) -> Result<i64, ApiError> {
+    time::parse_time(value, bound)
+        .ok_or_else(|| ApiError::BadRequest(format!("invalid {} time: {}", name, value)))
+}
+
+/// The value of a query parameter that is not one of the standard filters
+fn query_value(req: &Request, name: &str) -> Option<String> {
+    req.url
+        .as_ref()
+        .query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+}
+
+/// The `gap` parameter in minutes as seconds, or the default session gap
+fn session_gap(req: &Request) -> Result<i64, ApiError> {
+    match query_value(req, "gap") {
+        Some(value) => match value.parse::<i64>() {
+            Ok(minutes) if minutes > 0 => Ok(minutes * 60),
+            _ => Err(ApiError::BadRequest(format!(
+                "invalid gap, expected a positive number of minutes: {}",
+                value
+            ))),
+        },
+        None => Ok(sessions::DEFAULT_SESSION_GAP),
+    }
+}
 
-    Ok(match stats.get_user_msg_count() {
-        Ok(count) => Response::with((status::Ok, count.to_string())),
-        Err(_) => {
-            eprintln!("Error getting message count");
-            Response::with((status::NoContent, "0".to_owned()))
+fn stats(req: &mut Request) -> Result<Arc<StatsStore>, ApiError> {
+    req.get::<Read<Stats>>()
+        .map(|stats| Arc::clone(&*stats))
+        .map_err(|e| ApiError::Internal(e.to_string()))
+}
+
+/// A number of messages or edits
+#[derive(Serialize)]
+pub struct Count {
+    pub count: i64,
+}
+
+/// Messages sent on one day, split into guild and direct messages
+#[derive(Serialize)]
+pub struct DailyCount {
+    pub date: String,
+    pub public: i64,
+    pub private: i64,
+}
+
+impl From<DailyMessageCount> for DailyCount {
+    fn from(count: DailyMessageCount) -> Self {
+        DailyCount {
+            date: count.date,
+            public: count.public,
+            private: count.private,
         }
-    })
+    }
 }
 
-pub fn edit_count(req: &mut Request) -> IronResult<Response> {
-    let stats = req.get::<Read<Stats>>().unwrap();
+/// A channel with logged messages
+///
+/// Ids are strings as they do not fit in a javascript number.
+#[derive(Serialize)]
+pub struct ChannelInfo {
+    pub channel_id: String,
+    /// Missing for direct messages
+    pub guild_id: Option<String>,
+}
+
+/// Totals sent on the event stream after every change
+#[derive(Serialize)]
+pub struct Counters {
+    pub total: i64,
+    pub user: i64,
+    pub edits: i64,
+}
+
+/// Health of the collector and database
+#[derive(Serialize)]
+pub struct Status {
+    /// False if the database can not be written or the collector lost its connection
+    pub ok: bool,
+    /// Whether this process runs the Discord client, false for `serve`
+    pub collecting: bool,
+    pub gateway_connected: bool,
+    /// The user messages are recorded for, from the last connection
+    pub user: Option<StatusUser>,
+    /// Unix timestamp of the last gateway event
+    pub last_event: Option<i64>,
+    pub tracked_channels: u64,
+    pub schema_version: i64,
+    pub database_size: Option<u64>,
+    /// The database file is not read-only and the writer thread is running
+    pub database_writable: bool,
+    pub uptime_seconds: i64,
+}
+
+#[derive(Serialize)]
+pub struct StatusUser {
+    pub id: String,
+    pub name: Option<String>,
+}
+
+/// A guild with logged messages
+#[derive(Serialize)]
+pub struct GuildInfo {
+    pub guild_id: String,
+}
+
+/// A user and how many times they did something
+#[derive(Serialize)]
+pub struct UserCount {
+    pub user_id: String,
+    pub name: Option<String>,
+    pub count: usize,
+}
+
+/// Conversations found by splitting channels at pauses longer than the gap
+#[derive(Serialize)]
+pub struct SessionStats {
+    pub gap_seconds: i64,
+    pub sessions: usize,
+    pub median_length_seconds: i64,
+    pub mean_length_seconds: f64,
+    pub longest_seconds: i64,
+    pub mean_messages: f64,
+    /// Who sent the first message of sessions, most first
+    pub starters: Vec<UserCount>,
+}
+
+/// How quickly a user answers others within a session
+#[derive(Serialize)]
+pub struct ResponseTimeInfo {
+    pub user_id: String,
+    pub name: Option<String>,
+    pub responses: usize,
+    pub median_seconds: i64,
+}
 
-    Ok(match stats.get_edit_count() {
-        Ok(count) => Response::with((status::Ok, count.to_string())),
-        Err(e) => {
-            eprintln!("Error getting message count: {:?}", e);
-            Response::with((status::NoContent, "0".to_owned()))
+/// Words used in the messages' content, leaving out stopwords, links and code
+#[derive(Serialize)]
+pub struct WordStats {
+    pub messages: usize,
+    pub average_words: f64,
+    pub average_characters: f64,
+    pub words: Vec<TextCount>,
+    /// Pairs of adjacent words, separated by a space
+    pub bigrams: Vec<TextCount>,
+    pub vocabulary: Vec<VocabularyInfo>,
+    /// Words used more in one channel than in the others
+    pub channels: Vec<ChannelWordsInfo>,
+}
+
+#[derive(Serialize)]
+pub struct TextCount {
+    pub text: String,
+    pub count: usize,
+}
+
+#[derive(Serialize)]
+pub struct VocabularyInfo {
+    pub month: String,
+    pub distinct: usize,
+    pub new: usize,
+    pub cumulative: usize,
+}
+
+#[derive(Serialize)]
+pub struct ChannelWordsInfo {
+    pub channel_id: String,
+    pub words: Vec<ScoredWord>,
+}
+
+#[derive(Serialize)]
+pub struct ScoredWord {
+    pub text: String,
+    pub score: f64,
+}
+
+/// Unicode and custom emoji used in the messages' content
+#[derive(Serialize)]
+pub struct EmojiStats {
+    pub total: usize,
+    pub emoji: Vec<EmojiCount>,
+    pub users: Vec<UserEmoji>,
+    pub guilds: Vec<GuildEmoji>,
+    pub months: Vec<MonthCount>,
+}
+
+#[derive(Serialize)]
+pub struct EmojiCount {
+    /// The emoji itself, or the name of a custom emoji
+    pub emoji: String,
+    /// Only for custom emoji
+    pub id: Option<String>,
+    pub image_url: Option<String>,
+    pub count: usize,
+}
+
+impl EmojiCount {
+    fn new((emoji, count): (Emoji, usize)) -> Self {
+        let id = match &emoji {
+            Emoji::Custom { id, .. } => Some(id.0.to_string()),
+            Emoji::Unicode(_) => None,
+        };
+        EmojiCount {
+            emoji: emoji.name().to_owned(),
+            id,
+            image_url: emoji.image_url(),
+            count,
         }
-    })
+    }
 }
 
-pub fn msg_count_per_day(req: &mut Request) -> IronResult<Response> {
-    let stats = req.get::<Read<Stats>>().unwrap();
+#[derive(Serialize)]
+pub struct UserEmoji {
+    pub user_id: String,
+    pub name: Option<String>,
+    pub count: usize,
+    pub top: Vec<EmojiCount>,
+}
+
+#[derive(Serialize)]
+pub struct GuildEmoji {
+    pub guild_id: String,
+    pub count: usize,
+    pub top: Vec<EmojiCount>,
+}
+
+/// A count for a month, written as `YYYY-MM`
+#[derive(Serialize)]
+pub struct MonthCount {
+    pub month: String,
+    pub count: usize,
+}
+
+#[derive(Serialize)]
+pub struct DomainInfo {
+    pub domain: String,
+    pub count: i64,
+}
 
-    Ok(match stats.get_user_msgs_per_day() {
-        Ok(count) => Response::with((status::Ok, serde_json::to_string(&count).unwrap())),
-        Err(e) => {
-            eprintln!("Error getting message count: {:#?}", e);
-            Response::with((status::InternalServerError, "0".to_owned()))
+/// A link and how many messages shared it
+#[derive(Serialize)]
+pub struct LinkInfo {
+    pub url: String,
+    pub domain: String,
+    pub count: i64,
+    /// Unix timestamp of the first message with the link
+    pub first_shared: i64,
+}
+
+#[derive(Serialize)]
+pub struct DailyLinkCount {
+    pub date: String,
+    pub count: i64,
+}
+
+/// Fenced code blocks in the messages' content and the lines of code in them
+#[derive(Serialize)]
+pub struct CodeStats {
+    pub blocks: usize,
+    pub lines: usize,
+    pub languages: Vec<LanguageCode>,
+    pub users: Vec<UserCode>,
+    pub channels: Vec<ChannelCode>,
+    pub months: Vec<MonthCode>,
+}
+
+#[derive(Serialize)]
+pub struct LanguageCode {
+    /// Missing for blocks without a declared language
+    pub language: Option<String>,
+    pub blocks: usize,
+    pub lines: usize,
+}
+
+#[derive(Serialize)]
+pub struct UserCode {
+    pub user_id: String,
+    pub name: Option<String>,
+    pub blocks: usize,
+    pub lines: usize,
+}
+
+#[derive(Serialize)]
+pub struct ChannelCode {
+    pub channel_id: String,
+    pub blocks: usize,
+    pub lines: usize,
+}
+
+#[derive(Serialize)]
+pub struct MonthCode {
+    pub month: String,
+    pub blocks: usize,
+    pub lines: usize,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: String,
+    kind: &'a str,
+}
+
+impl ApiError {
+    fn status(&self) -> status::Status {
+        match self {
+            ApiError::Store(StoreError::Sqlite(rusqlite::Error::QueryReturnedNoRows)) => {
+                status::NotFound
+            }
+            ApiError::Store(StoreError::Sqlite(rusqlite::Error::SqliteFailure(e, _)))
+                if e.code == ErrorCode::DatabaseBusy || e.code == ErrorCode::DatabaseLocked =>
+            {
+                status::ServiceUnavailable
+            }
+            ApiError::Store(_) | ApiError::Serialization(_) | ApiError::Internal(_) => {
+                status::InternalServerError
+            }
+            ApiError::BadRequest(_) => status::BadRequest,
+            ApiError::Auth(AuthError::Unauthorized) => status::Unauthorized,
+            ApiError::Auth(AuthError::Forbidden) => status::Forbidden,
         }
-    })
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            ApiError::Store(StoreError::Sqlite(rusqlite::Error::QueryReturnedNoRows)) => {
+                "not_found"
+            }
+            ApiError::Store(_) => "database",
+            ApiError::Serialization(_) => "serialization",
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Auth(AuthError::Unauthorized) => "unauthorized",
+            ApiError::Auth(AuthError::Forbidden) => "forbidden",
+            ApiError::Internal(_) => "internal",
+        }
+    }
 }
 
-pub fn total_msg_count_per_day(req: &mut Request) -> IronResult<Response> {
-    let stats = req.get::<Read<Stats>>().unwrap();
+/// Builds the JSON response for an error
+pub fn error_response(err: &ApiError) -> Response {
+    let body = ErrorBody {
+        error: err.to_string(),
+        kind: err.kind(),
+    };
+    let mut resp = Response::with((
+        err.status(),
+        serde_json::to_string(&body).expect("error body is serializable"),
+    ));
+    resp.headers.set(iron::headers::ContentType::json());
+    resp
+}
 
-    Ok(match stats.get_total_msgs_per_day() {
-        Ok(count) => Response::with((status::Ok, serde_json::to_string(&count).unwrap())),
-        Err(_) => {
-            eprintln!("Error getting message count");
-            Response::with((status::InternalServerError, "0".to_owned()))
+impl From<ApiError> for IronError {
+    fn from(err: ApiError) -> IronError {
+        if err.status().is_server_error() {
+            error!("Error handling request: {:?}", err);
         }
-    })
+        let response = error_response(&err);
+        IronError {
+            error: Box::new(err),
+            response,
+        }
+    }
+}
+
+fn json_response<T: Serialize>(value: &T) -> IronResult<Response> {
+    let body = serde_json::to_string(value).map_err(ApiError::Serialization)?;
+    let mut resp = Response::with((status::Ok, body));
+    resp.headers.set(iron::headers::ContentType::json());
+    Ok(resp)
+}
+
+pub fn total_msg_count(req: &mut Request) -> IronResult<Response> {
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
+
+    let count = stats.get_msg_count(&filter).map_err(ApiError::from)?;
+    json_response(&Count { count })
+}
+
+pub fn msg_count(req: &mut Request) -> IronResult<Response> {
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
+
+    let count = stats.get_user_msg_count(&filter).map_err(ApiError::from)?;
+    json_response(&Count { count })
+}
+
+pub fn edit_count(req: &mut Request) -> IronResult<Response> {
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
+
+    let count = stats.get_edit_count(&filter).map_err(ApiError::from)?;
+    json_response(&Count { count })
+}
+
+pub fn msg_count_per_day(req: &mut Request) -> IronResult<Response> {
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
+
+    let counts = stats
+        .get_user_msgs_per_day(&filter)
+        .map_err(ApiError::from)?;
+    json_response(&counts.into_iter().map(DailyCount::from).collect::<Vec<_>>())
+}
+
+pub fn total_msg_count_per_day(req: &mut Request) -> IronResult<Response> {
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
+
+    let counts = stats
+        .get_total_msgs_per_day(&filter)
+        .map_err(ApiError::from)?;
+    json_response(&counts.into_iter().map(DailyCount::from).collect::<Vec<_>>())
 }
 
 pub fn get_channels(req: &mut Request) -> IronResult<Response> {
-    let stats = req.get::<Read<Stats>>().unwrap();
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
 
-    Ok(match stats.get_channels() {
-        Ok(ref channels) => Response::with((status::Ok, serde_json::to_string(channels).unwrap())),
-        Err(_) => {
-            eprintln!("Error getting channels");
-            Response::with((status::InternalServerError, "[]"))
-        }
-    })
+    let channels = stats.get_channels(&filter).map_err(ApiError::from)?;
+    json_response(
+        &channels
+            .into_iter()
+            .map(|channel| ChannelInfo {
+                channel_id: channel.channel_id.0.to_string(),
+                guild_id: channel.guild_id.map(|guild_id| guild_id.0.to_string()),
+            })
+            .collect::<Vec<_>>(),
+    )
 }
 
 pub fn get_guilds(req: &mut Request) -> IronResult<Response> {
-    let stats = req.get::<Read<Stats>>().unwrap();
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
 
-    Ok(match stats.get_guilds() {
-        Ok(ref guilds) => Response::with((status::Ok, serde_json::to_string(guilds).unwrap())),
-        Err(_) => {
-            eprintln!("Error getting guilds");
-            Response::with((status::InternalServerError, "[]"))
-        }
+    let guilds = stats.get_guilds(&filter).map_err(ApiError::from)?;
+    json_response(
+        &guilds
+            .into_iter()
+            .map(|guild_id| GuildInfo {
+                guild_id: guild_id.0.to_string(),
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Conversation sessions, split at pauses longer than `gap` minutes
+pub fn get_sessions(req: &mut Request) -> IronResult<Response> {
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
+    let gap = session_gap(req)?;
+
+    let timeline = stats.get_timeline(&filter).map_err(ApiError::from)?;
+    let names = stats.get_user_names().map_err(ApiError::from)?;
+    let summary = sessions::summarize(&sessions::sessions(&timeline, gap));
+
+    json_response(&SessionStats {
+        gap_seconds: gap,
+        sessions: summary.sessions,
+        median_length_seconds: summary.median_length,
+        mean_length_seconds: summary.mean_length,
+        longest_seconds: summary.longest,
+        mean_messages: summary.mean_messages,
+        starters: summary
+            .starters
+            .into_iter()
+            .map(|(user_id, count)| UserCount {
+                user_id: user_id.0.to_string(),
+                name: names.get(&user_id).cloned(),
+                count,
+            })
+            .collect(),
     })
 }
 
+/// Median time users take to answer others, answers after a pause of `gap` minutes excluded
+pub fn get_response_times(req: &mut Request) -> IronResult<Response> {
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
+    let gap = session_gap(req)?;
+
+    let timeline = stats.get_timeline(&filter).map_err(ApiError::from)?;
+    let names = stats.get_user_names().map_err(ApiError::from)?;
+    json_response(
+        &sessions::response_times(&timeline, gap)
+            .into_iter()
+            .map(|time| ResponseTimeInfo {
+                user_id: time.user_id.0.to_string(),
+                name: names.get(&time.user_id).cloned(),
+                responses: time.responses,
+                median_seconds: time.median,
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Word, bigram and vocabulary statistics of the messages' content
+pub fn get_words(req: &mut Request) -> IronResult<Response> {
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
+    let config = req
+        .get::<Read<Analysis>>()
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+
+    let mut analysis = WordAnalysis::new(&config.stopwords);
+    stats
+        .for_each_content(&filter, |msg| analysis.add(&msg))
+        .map_err(ApiError::from)?;
+
+    let text_counts = |counts: Vec<(String, usize)>| {
+        counts
+            .into_iter()
+            .map(|(text, count)| TextCount { text, count })
+            .collect()
+    };
+    json_response(&WordStats {
+        messages: analysis.messages(),
+        average_words: analysis.average_words(),
+        average_characters: analysis.average_characters(),
+        words: text_counts(analysis.top_words(words::TOP_WORDS)),
+        bigrams: text_counts(analysis.top_bigrams(words::TOP_WORDS)),
+        vocabulary: analysis
+            .vocabulary()
+            .into_iter()
+            .map(|month| VocabularyInfo {
+                month: month.month,
+                distinct: month.distinct,
+                new: month.new,
+                cumulative: month.cumulative,
+            })
+            .collect(),
+        channels: analysis
+            .channel_words()
+            .into_iter()
+            .map(|channel| ChannelWordsInfo {
+                channel_id: channel.channel_id.0.to_string(),
+                words: channel
+                    .words
+                    .into_iter()
+                    .map(|(text, score)| ScoredWord { text, score })
+                    .collect(),
+            })
+            .collect(),
+    })
+}
+
+/// Emoji used in the messages' content, overall, per user, per guild and per month
+pub fn get_emoji(req: &mut Request) -> IronResult<Response> {
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
+
+    let mut analysis = EmojiAnalysis::new();
+    stats
+        .for_each_content(&filter, |msg| analysis.add(&msg))
+        .map_err(ApiError::from)?;
+    let names = stats.get_user_names().map_err(ApiError::from)?;
+
+    let emoji_counts = |top: Vec<(Emoji, usize)>| top.into_iter().map(EmojiCount::new).collect();
+    json_response(&EmojiStats {
+        total: analysis.total(),
+        emoji: emoji_counts(analysis.top(emoji::TOP_EMOJI)),
+        users: analysis
+            .users()
+            .into_iter()
+            .map(|user| UserEmoji {
+                user_id: user.id.0.to_string(),
+                name: names.get(&user.id).cloned(),
+                count: user.count,
+                top: emoji_counts(user.top),
+            })
+            .collect(),
+        guilds: analysis
+            .guilds()
+            .into_iter()
+            .map(|guild| GuildEmoji {
+                guild_id: guild.id.0.to_string(),
+                count: guild.count,
+                top: emoji_counts(guild.top),
+            })
+            .collect(),
+        months: analysis
+            .months()
+            .into_iter()
+            .map(|(month, count)| MonthCount { month, count })
+            .collect(),
+    })
+}
+
+/// Sites links were shared to, with short links and mirrors grouped under one domain
+pub fn link_domains(req: &mut Request) -> IronResult<Response> {
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
+
+    let domains = stats
+        .get_link_domains(&filter, TOP_LINKS)
+        .map_err(ApiError::from)?;
+    json_response(
+        &domains
+            .into_iter()
+            .map(|domain| DomainInfo {
+                domain: domain.domain,
+                count: domain.count,
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+pub fn top_links(req: &mut Request) -> IronResult<Response> {
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
+
+    let links = stats
+        .get_top_links(&filter, TOP_LINKS)
+        .map_err(ApiError::from)?;
+    json_response(
+        &links
+            .into_iter()
+            .map(|link| LinkInfo {
+                url: link.url,
+                domain: link.domain,
+                count: link.count,
+                first_shared: link.first_shared,
+            })
+            .collect::<Vec<_>>(),
+    )
+}
+
+pub fn links_per_day(req: &mut Request) -> IronResult<Response> {
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
+
+    let counts = stats.get_links_per_day(&filter).map_err(ApiError::from)?;
+    json_response(
+        &counts
+            .into_iter()
+            .map(|(date, count)| DailyLinkCount { date, count })
+            .collect::<Vec<_>>(),
+    )
+}
+
+/// Code blocks and lines of code per language, user, channel and month
+pub fn get_code(req: &mut Request) -> IronResult<Response> {
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
+
+    let mut analysis = CodeAnalysis::new();
+    stats
+        .for_each_content(&filter, |msg| analysis.add(&msg))
+        .map_err(ApiError::from)?;
+    let names = stats.get_user_names().map_err(ApiError::from)?;
+
+    let CodeCount { blocks, lines } = analysis.total();
+    json_response(&CodeStats {
+        blocks,
+        lines,
+        languages: analysis
+            .languages()
+            .into_iter()
+            .map(|(language, count)| LanguageCode {
+                language,
+                blocks: count.blocks,
+                lines: count.lines,
+            })
+            .collect(),
+        users: analysis
+            .users()
+            .into_iter()
+            .map(|(user_id, count)| UserCode {
+                user_id: user_id.0.to_string(),
+                name: names.get(&user_id).cloned(),
+                blocks: count.blocks,
+                lines: count.lines,
+            })
+            .collect(),
+        channels: analysis
+            .channels()
+            .into_iter()
+            .map(|(channel_id, count)| ChannelCode {
+                channel_id: channel_id.0.to_string(),
+                blocks: count.blocks,
+                lines: count.lines,
+            })
+            .collect(),
+        months: analysis
+            .months()
+            .into_iter()
+            .map(|(month, count)| MonthCode {
+                month,
+                blocks: count.blocks,
+                lines: count.lines,
+            })
+            .collect(),
+    })
+}
+
+/// Who mentions and replies to whom, as JSON or in the format given by `format`
+pub fn interaction_graph(req: &mut Request) -> IronResult<Response> {
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
+
+    let format = match query_value(req, "format") {
+        Some(value) => value.parse().map_err(ApiError::BadRequest)?,
+        None => GraphFormat::Json,
+    };
+
+    let graph = stats
+        .get_interaction_graph(&filter)
+        .map_err(ApiError::from)?;
+    if format == GraphFormat::Json {
+        return json_response(&GraphDocument::from(&graph));
+    }
+
+    let body = graph::render(&graph, format).map_err(ApiError::Serialization)?;
+    let mut resp = Response::with((status::Ok, body));
+    resp.headers.set_raw(
+        "Content-Type",
+        vec![format.content_type().as_bytes().to_vec()],
+    );
+    Ok(resp)
+}
+
+/// Streams changes to the store as server-sent events
+///
+/// Each connection holds one of the web server's threads until the client goes away.
+pub fn events(req: &mut Request) -> IronResult<Response> {
+    let stats = stats(req)?;
+    let filter = request_filter(req)?;
+    let bus = req
+        .get::<Read<Events>>()
+        .map_err(|e| ApiError::Internal(e.to_string()))?;
+
+    let stream = EventStream {
+        events: bus.subscribe(),
+        stats,
+        filter,
+    };
+    let mut resp = Response::with((status::Ok, Box::new(stream) as Box<dyn WriteBody>));
+    resp.headers
+        .set_raw("Content-Type", vec![b"text/event-stream".to_vec()]);
+    resp.headers
+        .set(CacheControl(vec![CacheDirective::NoCache]));
+    // Keeps nginx from buffering the stream
+    resp.headers
+        .set_raw("X-Accel-Buffering", vec![b"no".to_vec()]);
+    Ok(resp)
+}
+
+struct EventStream {
+    events: Receiver<LiveEvent>,
+    stats: Arc<StatsStore>,
+    filter: Filter,
+}
+
+impl EventStream {
+    fn is_visible(&self, event: &LiveEvent) -> bool {
+        let guild_visible = match self.filter.guilds {
+            Some(ref guilds) => event
+                .guild_id()
+                .and_then(|id| id.parse().ok())
+                .map_or(false, |id| guilds.contains(&GuildId(id))),
+            None => true,
+        };
+        let channel_visible = match self.filter.channels {
+            Some(ref channels) => event
+                .channel_id()
+                .parse()
+                .map(|id| channels.contains(&ChannelId(id)))
+                .unwrap_or(false),
+            None => true,
+        };
+        guild_visible && channel_visible
+    }
+
+    fn counters(&self) -> Result<Counters, StoreError> {
+        Ok(Counters {
+            total: self.stats.get_msg_count(&self.filter)?,
+            user: self.stats.get_user_msg_count(&self.filter)?,
+            edits: self.stats.get_edit_count(&self.filter)?,
+        })
+    }
+}
+
+fn write_event<T: Serialize>(out: &mut dyn Write, name: &str, data: &T) -> io::Result<()> {
+    let data = serde_json::to_string(data)?;
+    write!(out, "event: {}\ndata: {}\n\n", name, data)
+}
+
+impl WriteBody for EventStream {
+    fn write_body(&mut self, res: &mut dyn Write) -> io::Result<()> {
+        // Browsers reconnect after this many milliseconds if the stream breaks
+        res.write_all(b"retry: 5000\n\n")?;
+        res.flush()?;
+
+        loop {
+            match self.events.recv_timeout(KEEPALIVE_INTERVAL) {
+                Ok(event) => {
+                    if !self.is_visible(&event) {
+                        continue;
+                    }
+                    write_event(res, event.name(), &event)?;
+                    match self.counters() {
+                        Ok(counters) => write_event(res, "counters", &counters)?,
+                        Err(e) => error!("Error getting counters for event stream: {:?}", e),
+                    }
+                }
+                Err(RecvTimeoutError::Timeout) => res.write_all(b": keepalive\n\n")?,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+            res.flush()?;
+        }
+    }
+}
+
+/// Health check, which responds with 503 if the status is not ok
+pub fn status(req: &mut Request) -> IronResult<Response> {
+    require_full_scope(req)?;
+    let stats = stats(req)?;
+    let m = &METRICS;
+
+    let collecting = m.collecting.load(Ordering::Relaxed);
+    let gateway_connected = m.gateway_connected.load(Ordering::Relaxed);
+    let database_writable = stats.is_writable();
+    let status = Status {
+        ok: database_writable && (gateway_connected || !collecting),
+        collecting,
+        gateway_connected,
+        user: stats
+            .current_user()
+            .map_err(ApiError::from)?
+            .map(|(id, name)| StatusUser {
+                id: id.0.to_string(),
+                name,
+            }),
+        last_event: m.last_event(),
+        tracked_channels: m.tracked_channels.load(Ordering::Relaxed),
+        schema_version: stats.schema_version().map_err(ApiError::from)?,
+        database_size: stats.database_size().ok(),
+        database_writable,
+        uptime_seconds: m.uptime(),
+    };
+
+    let mut resp = json_response(&status)?;
+    if !status.ok {
+        resp.status = Some(status::ServiceUnavailable);
+    }
+    Ok(resp)
+}
+
+/// Prometheus metrics in the text exposition format
+pub fn prometheus_metrics(req: &mut Request) -> IronResult<Response> {
+    require_full_scope(req)?;
+    let stats = stats(req)?;
+
+    let mut resp = Response::with((status::Ok, metrics::render(&stats)));
+    resp.headers
+        .set_raw("Content-Type", vec![b"text/plain; version=0.0.4".to_vec()]);
+    Ok(resp)
+}
+
+pub fn openapi(_req: &mut Request) -> IronResult<Response> {
+    json_response(&openapi::document())
+}
+
 #[cfg(not(debug_assertions))]
 pub fn dashboard(_rq: &mut Request) -> IronResult<Response> {
     let mut resp = Response::with((status::Ok, DASHBOARD_SOURCE));
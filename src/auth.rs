@@ -0,0 +1,123 @@
+use iron::headers::{Authorization, Basic, Bearer};
+use iron::method::Method;
+use iron::middleware::BeforeMiddleware;
+use iron::prelude::*;
+use iron::typemap::Key;
+use serenity::model::id::GuildId;
+
+use crate::config::AuthConfig;
+use crate::error::{ApiError, AuthError};
+
+/// What the credentials of a request allow it to access
+#[derive(Clone, Debug)]
+pub struct Scope {
+    /// Guilds the request may read, `None` for all guilds and direct messages
+    pub guilds: Option<Vec<GuildId>>,
+    pub read_only: bool,
+}
+
+impl Scope {
+    fn full() -> Scope {
+        Scope {
+            guilds: None,
+            read_only: false,
+        }
+    }
+}
+
+impl Key for Scope {
+    type Value = Scope;
+}
+
+/// Checks the credentials of every request and records its `Scope`
+pub struct Authenticator {
+    config: AuthConfig,
+}
+
+impl Authenticator {
+    pub fn new(config: AuthConfig) -> Authenticator {
+        Authenticator { config }
+    }
+
+    fn authenticate(&self, req: &Request) -> Option<Scope> {
+        if !self.config.is_enabled() {
+            return Some(Scope::full());
+        }
+
+        if let Some(Authorization(Bearer { token })) = req.headers.get::<Authorization<Bearer>>() {
+            if let Some(ref full_token) = self.config.bearer_token {
+                if constant_time_eq(token.as_bytes(), full_token.as_bytes()) {
+                    return Some(Scope::full());
+                }
+            }
+
+            return self
+                .config
+                .api_keys
+                .iter()
+                .find(|api_key| constant_time_eq(token.as_bytes(), api_key.key.as_bytes()))
+                .map(|api_key| Scope {
+                    guilds: if api_key.guilds.is_empty() {
+                        None
+                    } else {
+                        Some(api_key.guilds.clone())
+                    },
+                    read_only: true,
+                });
+        }
+
+        if let (Some(Authorization(basic)), Some(ref expected)) = (
+            req.headers.get::<Authorization<Basic>>(),
+            &self.config.basic,
+        ) {
+            let password = basic.password.as_ref().map(String::as_str).unwrap_or("");
+            // Compare both so a wrong username takes as long as a wrong password
+            let username_ok =
+                constant_time_eq(basic.username.as_bytes(), expected.username.as_bytes());
+            let password_ok = constant_time_eq(password.as_bytes(), expected.password.as_bytes());
+            if username_ok && password_ok {
+                return Some(Scope::full());
+            }
+        }
+
+        None
+    }
+
+    fn challenge(&self) -> &'static [u8] {
+        if self.config.basic.is_some() {
+            b"Basic realm=\"Discord Statistics\""
+        } else {
+            b"Bearer"
+        }
+    }
+}
+
+impl BeforeMiddleware for Authenticator {
+    fn before(&self, req: &mut Request) -> IronResult<()> {
+        let scope = match self.authenticate(req) {
+            Some(scope) => scope,
+            None => {
+                let mut err = IronError::from(ApiError::Auth(AuthError::Unauthorized));
+                err.response
+                    .headers
+                    .set_raw("WWW-Authenticate", vec![self.challenge().to_vec()]);
+                return Err(err);
+            }
+        };
+
+        if scope.read_only && req.method != Method::Get && req.method != Method::Head {
+            return Err(ApiError::Auth(AuthError::Forbidden).into());
+        }
+
+        req.extensions.insert::<Scope>(scope);
+        Ok(())
+    }
+}
+
+/// Compares secrets without exiting early on the first differing byte
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -0,0 +1,260 @@
+use serde_derive::{Deserialize, Serialize};
+use serenity::model::id::{ChannelId, GuildId};
+use std::fs::DirBuilder;
+use std::path::{Path, PathBuf};
+
+use crate::error::ConfigError;
+use crate::logging::LogFormat;
+
+/// File name of the sqlite database inside the data directory
+pub const DATABASE_FILE_NAME: &str = "store.sqlite3";
+
+#[derive(Serialize, Deserialize)]
+pub struct Config {
+    pub discord_token: String,
+    pub tracked_channels: Vec<String>,
+    #[serde(default)]
+    pub web: WebConfig,
+    #[serde(default)]
+    pub log: LogConfig,
+    #[serde(default)]
+    pub analysis: AnalysisConfig,
+
+    /// Path the configuration was loaded from, used when saving
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            discord_token: String::new(),
+            tracked_channels: Vec::new(),
+            web: WebConfig::default(),
+            log: LogConfig::default(),
+            analysis: AnalysisConfig::default(),
+            path: PathBuf::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration at `path`, creating a default one if it does not exist
+    pub fn load(path: &Path) -> Result<Config, ConfigError> {
+        let mut config = if !path.exists() {
+            create_parent_dir(path)?;
+            let conf = Config::default();
+            std::fs::write(
+                path,
+                toml::to_string(&conf).expect("configuration is serializable"),
+            )?;
+            conf
+        } else {
+            let config_str = std::fs::read_to_string(path)?;
+            toml::from_str(&config_str)?
+        };
+        config.path = path.to_owned();
+
+        Ok(config)
+    }
+
+    pub fn save(&self) -> Result<(), ConfigError> {
+        std::fs::write(
+            &self.path,
+            toml::to_string(self).expect("configuration is serializable"),
+        )?;
+        Ok(())
+    }
+
+    pub fn tracked_channels(&self) -> Result<Vec<(Option<GuildId>, ChannelId)>, ConfigError> {
+        let mut out = Vec::new();
+        for channel in &self.tracked_channels {
+            let chan = if channel.contains('|') {
+                let mut split_item = channel.split('|');
+                let guild = split_item.next().ok_or(ConfigError::InvalidGuildFormat)?;
+                let channel = split_item.next().ok_or(ConfigError::InvalidChannelFormat)?;
+                (
+                    Some(GuildId(
+                        guild
+                            .parse::<u64>()
+                            .map_err(|_| ConfigError::InvalidChannelFormat)?,
+                    )),
+                    channel
+                        .parse()
+                        .map_err(|_| ConfigError::InvalidChannelFormat)?,
+                )
+            } else {
+                (
+                    None,
+                    channel
+                        .parse()
+                        .map_err(|_| ConfigError::InvalidChannelFormat)?,
+                )
+            };
+            out.push(chan);
+        }
+        Ok(out)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct WebConfig {
+    /// Whether the collector also runs the web server
+    pub enabled: bool,
+    pub bind_address: String,
+    pub port: u16,
+    /// Path prefix the dashboard and API are served under, for reverse proxies
+    pub base_path: String,
+    pub auth: AuthConfig,
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        WebConfig {
+            enabled: true,
+            bind_address: "localhost".to_owned(),
+            port: 8080,
+            base_path: String::new(),
+            auth: AuthConfig::default(),
+        }
+    }
+}
+
+impl WebConfig {
+    /// The base path with a leading slash and no trailing slash, or empty for the root
+    pub fn base_path(&self) -> String {
+        let trimmed = self.base_path.trim_matches('/');
+        if trimmed.is_empty() {
+            String::new()
+        } else {
+            format!("/{}", trimmed)
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Filter such as `info` or `warn,discord_statistics::scan=debug`,
+    /// overridden by the `DISCORD_STATS_LOG` environment variable
+    pub level: String,
+    pub format: LogFormat,
+    /// File to append logs to instead of stderr
+    pub file: Option<PathBuf>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            level: "warn,discord_statistics=info".to_owned(),
+            format: LogFormat::Plain,
+            file: None,
+        }
+    }
+}
+
+/// Settings for the statistics computed from message contents
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AnalysisConfig {
+    /// Words left out of word statistics, replacing the built-in English list
+    pub stopwords: Option<Vec<String>>,
+}
+
+/// Credentials required to access the web server, which is open if none are set
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AuthConfig {
+    /// Token with full access, sent as `Authorization: Bearer <token>`
+    pub bearer_token: Option<String>,
+    /// Read-only keys, also sent as bearer tokens
+    pub api_keys: Vec<ApiKeyConfig>,
+    /// HTTP basic auth credentials with full access, for the dashboard
+    pub basic: Option<BasicAuthConfig>,
+}
+
+impl AuthConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.bearer_token.is_some() || self.basic.is_some() || !self.api_keys.is_empty()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BasicAuthConfig {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ApiKeyConfig {
+    pub key: String,
+    /// Guilds the key can read, all guilds and direct messages if empty
+    #[serde(default)]
+    pub guilds: Vec<GuildId>,
+}
+
+/// Default location of the configuration file
+pub fn default_config_path() -> Option<PathBuf> {
+    config_root().map(|h| h.join("config.toml"))
+}
+
+/// Default directory for the database and other generated data
+#[cfg(target_os = "macos")]
+pub fn default_data_dir() -> Option<PathBuf> {
+    dirs::home_dir().map(|h| h.join(".local/share/discord-statistics/"))
+}
+
+/// Default directory for the database and other generated data
+///
+/// Follows `$XDG_DATA_HOME` on Linux
+#[cfg(not(target_os = "macos"))]
+pub fn default_data_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|h| h.join("discord-statistics/"))
+}
+
+#[cfg(target_os = "macos")]
+fn config_root() -> Option<PathBuf> {
+    dirs::home_dir().map(|h| h.join(".config/discord-statistics/"))
+}
+
+#[cfg(not(target_os = "macos"))]
+fn config_root() -> Option<PathBuf> {
+    dirs::config_dir().map(|h| h.join("discord-statistics/"))
+}
+
+/// Moves a database left in the config directory by older versions to `db_path`
+///
+/// Nothing is done if `db_path` already exists or is not in the default data dir, as a
+/// database chosen with `--data-dir` is not the one older versions used. Returns the
+/// previous location if a database was moved.
+pub fn migrate_legacy_database(db_path: &Path) -> Result<Option<PathBuf>, ConfigError> {
+    let default_path = default_data_dir().map(|dir| dir.join(DATABASE_FILE_NAME));
+    if default_path.as_ref().map(PathBuf::as_path) != Some(db_path) {
+        return Ok(None);
+    }
+    let legacy_path = match config_root() {
+        Some(root) => root.join(DATABASE_FILE_NAME),
+        None => return Ok(None),
+    };
+
+    if db_path.exists() || !legacy_path.exists() || legacy_path == db_path {
+        return Ok(None);
+    }
+
+    create_parent_dir(db_path)?;
+    if std::fs::rename(&legacy_path, db_path).is_err() {
+        // rename fails across filesystems, fall back to copying
+        std::fs::copy(&legacy_path, db_path)?;
+        std::fs::remove_file(&legacy_path)?;
+    }
+
+    Ok(Some(legacy_path))
+}
+
+fn create_parent_dir(path: &Path) -> Result<(), ConfigError> {
+    DirBuilder::new()
+        .recursive(true)
+        .create(path.parent().ok_or(ConfigError::NoParent)?)?;
+    Ok(())
+}
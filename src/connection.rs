@@ -0,0 +1,250 @@
+use log::error;
+use rusqlite::{Connection, OpenFlags, NO_PARAMS};
+use serenity::prelude::Mutex;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::StoreError;
+
+/// Most writes committed in one transaction
+const MAX_BATCH_SIZE: usize = 256;
+/// Most idle read connections kept open
+const MAX_IDLE_READERS: usize = 8;
+/// How long a connection waits for a lock before failing with `DatabaseBusy`
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Runs a write on the writer's connection, returning whether it succeeded and what to do
+/// once it is committed
+type Job = Box<dyn FnOnce(&Connection) -> (bool, Reply) + Send>;
+/// Receives the result of committing the transaction a job ran in
+type Reply = Box<dyn FnOnce(Result<(), &StoreError>) + Send>;
+
+enum Request {
+    Write(Job),
+    /// Commits everything sent before and checkpoints the write-ahead log
+    Checkpoint(Sender<Result<(), StoreError>>),
+    /// Does nothing, sent to check that the writer thread is still running
+    Ping,
+}
+
+/// Opens a connection with the settings shared by the writer and readers
+pub fn open(path: &Path, flags: OpenFlags) -> Result<Connection, StoreError> {
+    let conn = Connection::open_with_flags(path, flags)?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    Ok(conn)
+}
+
+/// Switches the database to write-ahead logging so reads do not wait for writes
+///
+/// The journal mode is stored in the database, so this only has to succeed once.
+pub fn enable_wal(conn: &Connection) -> Result<(), StoreError> {
+    // The pragmas return a row, so they can not be run with `execute`
+    conn.query_row("PRAGMA journal_mode = WAL", NO_PARAMS, |_| Ok(()))?;
+    // Safe with a write-ahead log, committed transactions survive crashes of the process
+    conn.execute_batch("PRAGMA synchronous = NORMAL")?;
+    Ok(())
+}
+
+/// The single connection writing to the database, running on its own thread
+///
+/// Writes sent while another batch is committed are grouped into one transaction. Each
+/// runs in a savepoint, so a failing write is rolled back without affecting the others.
+pub struct Writer {
+    requests: Mutex<Sender<Request>>,
+}
+
+impl Writer {
+    pub fn new(conn: Connection) -> Writer {
+        let (tx, rx) = mpsc::channel();
+        thread::Builder::new()
+            .name("database writer".to_owned())
+            .spawn(move || run_writer(&conn, &rx))
+            .expect("unable to spawn database writer");
+
+        Writer {
+            requests: Mutex::new(tx),
+        }
+    }
+
+    /// Runs `job` in the next transaction, returning once it is committed
+    pub fn write<T, F>(&self, job: F) -> Result<T, StoreError>
+    where
+        F: FnOnce(&Connection) -> Result<T, StoreError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let job: Job = Box::new(move |conn| {
+            let result = job(conn);
+            let succeeded = result.is_ok();
+            let reply: Reply = Box::new(move |committed: Result<(), &StoreError>| {
+                let result = match committed {
+                    Ok(()) => result,
+                    // A failed job was already rolled back, report why it failed
+                    Err(e) => result.and_then(|_| Err(StoreError::Commit(e.to_string()))),
+                };
+                let _ = tx.send(result);
+            });
+            (succeeded, reply)
+        });
+
+        self.send(Request::Write(job))?;
+        rx.recv().map_err(|_| StoreError::WriterStopped)?
+    }
+
+    /// Waits for all pending writes and checkpoints the write-ahead log
+    pub fn checkpoint(&self) -> Result<(), StoreError> {
+        let (tx, rx) = mpsc::channel();
+        self.send(Request::Checkpoint(tx))?;
+        rx.recv().map_err(|_| StoreError::WriterStopped)?
+    }
+
+    /// Whether the writer thread still takes writes, without waiting for pending ones
+    pub fn is_running(&self) -> bool {
+        self.send(Request::Ping).is_ok()
+    }
+
+    fn send(&self, request: Request) -> Result<(), StoreError> {
+        self.requests
+            .lock()
+            .send(request)
+            .map_err(|_| StoreError::WriterStopped)
+    }
+}
+
+fn run_writer(conn: &Connection, requests: &Receiver<Request>) {
+    let mut pending = requests.recv().ok();
+
+    while let Some(request) = pending.take() {
+        match request {
+            Request::Write(job) => {
+                let mut jobs = vec![job];
+                // Take whatever queued up while the last batch was written
+                while jobs.len() < MAX_BATCH_SIZE {
+                    match requests.try_recv() {
+                        Ok(Request::Write(job)) => jobs.push(job),
+                        Ok(other) => {
+                            pending = Some(other);
+                            break;
+                        }
+                        Err(_) => break,
+                    }
+                }
+                write_batch(conn, jobs);
+            }
+            Request::Checkpoint(reply) => {
+                let result = conn
+                    .query_row("PRAGMA wal_checkpoint(TRUNCATE)", NO_PARAMS, |_| Ok(()))
+                    .map_err(Into::into);
+                let _ = reply.send(result);
+            }
+            Request::Ping => {}
+        }
+
+        if pending.is_none() {
+            pending = requests.recv().ok();
+        }
+    }
+}
+
+fn write_batch(conn: &Connection, jobs: Vec<Job>) {
+    if let Err(e) = conn.execute_batch("BEGIN IMMEDIATE") {
+        error!("Unable to start a write transaction: {}", e);
+        // Fall back to committing each write on its own
+        for job in jobs {
+            let (_, reply) = job(conn);
+            reply(Ok(()));
+        }
+        return;
+    }
+
+    let replies: Vec<Reply> = jobs
+        .into_iter()
+        .map(|job| {
+            if let Err(e) = conn.execute_batch("SAVEPOINT job") {
+                error!("Unable to create savepoint: {}", e);
+            }
+            let (succeeded, reply) = job(conn);
+            let end = if succeeded {
+                "RELEASE job"
+            } else {
+                // Undo partial writes of the failed job only
+                "ROLLBACK TO job; RELEASE job"
+            };
+            if let Err(e) = conn.execute_batch(end) {
+                error!("Unable to end savepoint: {}", e);
+            }
+            reply
+        })
+        .collect();
+
+    match conn.execute_batch("COMMIT") {
+        Ok(()) => replies.into_iter().for_each(|reply| reply(Ok(()))),
+        Err(e) => {
+            let _ = conn.execute_batch("ROLLBACK");
+            let e = StoreError::from(e);
+            error!("Unable to commit {} writes: {}", replies.len(), e);
+            replies.into_iter().for_each(|reply| reply(Err(&e)));
+        }
+    }
+}
+
+/// Read-only connections shared by the web server's threads
+pub struct ReadPool {
+    path: PathBuf,
+    idle: Mutex<Vec<Connection>>,
+}
+
+impl ReadPool {
+    pub fn new(path: &Path) -> ReadPool {
+        ReadPool {
+            path: path.to_owned(),
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Takes an idle connection or opens a new one, it is returned to the pool on drop
+    pub fn get(&self) -> Result<PooledConnection, StoreError> {
+        let idle = self.idle.lock().pop();
+        let conn = match idle {
+            Some(conn) => conn,
+            None => open(
+                &self.path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )?,
+        };
+
+        Ok(PooledConnection {
+            pool: self,
+            conn: Some(conn),
+        })
+    }
+}
+
+pub struct PooledConnection<'a> {
+    pool: &'a ReadPool,
+    conn: Option<Connection>,
+}
+
+impl<'a> Deref for PooledConnection<'a> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn
+            .as_ref()
+            .expect("connection is only taken on drop")
+    }
+}
+
+impl<'a> Drop for PooledConnection<'a> {
+    fn drop(&mut self) {
+        let mut idle = self.pool.idle.lock();
+        if let Some(conn) = self.conn.take() {
+            if idle.len() < MAX_IDLE_READERS {
+                idle.push(conn);
+            }
+        }
+    }
+}
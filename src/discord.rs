@@ -0,0 +1,333 @@
+use chrono::DateTime;
+use log::warn;
+use reqwest::header::{HeaderMap, AUTHORIZATION};
+use reqwest::{Client, StatusCode};
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::RestError;
+use crate::store::NewMessage;
+
+const API_BASE: &str = "https://discord.com/api/v9";
+/// Most messages or threads Discord returns per request
+pub const PAGE_SIZE: u64 = 100;
+/// Message type of replies, other types also reference messages, such as pins
+const REPLY_MESSAGE_TYPE: u64 = 19;
+/// Channel types of threads in text and news channels and of posts in forum channels
+const THREAD_CHANNEL_TYPES: &[u64] = &[10, 11, 12];
+/// Channel types of text, news and forum channels
+const TEXT_CHANNEL_TYPES: &[u64] = &[0, 5, FORUM_CHANNEL_TYPE];
+const FORUM_CHANNEL_TYPE: u64 = 15;
+
+/// Requests to Discord's REST API for data the serenity version in use can not represent,
+/// such as threads
+///
+/// Rate limited requests are retried after the time Discord asks for.
+pub struct RestClient {
+    client: Client,
+    token: String,
+}
+
+/// A text, news or forum channel of a guild
+///
+/// Forum channels are unknown to the serenity version in use.
+#[derive(Clone, Debug)]
+pub struct TextChannel {
+    pub id: ChannelId,
+    pub name: String,
+    pub position: i64,
+    /// Forum channels have no messages of their own, only posts
+    pub forum: bool,
+}
+
+/// A thread or forum post and the channel it belongs to
+#[derive(Clone, Debug)]
+pub struct Thread {
+    pub id: ChannelId,
+    pub parent_id: ChannelId,
+    pub guild_id: Option<GuildId>,
+    pub name: String,
+}
+
+#[derive(Deserialize)]
+struct RawUser {
+    id: UserId,
+    username: String,
+}
+
+#[derive(Deserialize)]
+struct RawMessage {
+    id: MessageId,
+    #[serde(rename = "type", default)]
+    kind: u64,
+    channel_id: ChannelId,
+    author: RawUser,
+    #[serde(default)]
+    content: String,
+    timestamp: String,
+    edited_timestamp: Option<String>,
+    #[serde(default)]
+    mentions: Vec<RawUser>,
+    #[serde(default)]
+    mention_roles: Vec<RoleId>,
+    message_reference: Option<MessageReference>,
+    /// `None` if the message was deleted
+    referenced_message: Option<ReferencedMessage>,
+}
+
+#[derive(Deserialize)]
+struct MessageReference {
+    message_id: Option<MessageId>,
+}
+
+#[derive(Deserialize)]
+struct ReferencedMessage {
+    author: RawUser,
+}
+
+#[derive(Deserialize)]
+struct RawChannel {
+    id: ChannelId,
+    #[serde(rename = "type")]
+    kind: u64,
+    guild_id: Option<GuildId>,
+    parent_id: Option<ChannelId>,
+    name: Option<String>,
+    #[serde(default)]
+    position: i64,
+    thread_metadata: Option<ThreadMetadata>,
+}
+
+#[derive(Deserialize)]
+struct ThreadMetadata {
+    archive_timestamp: String,
+}
+
+#[derive(Deserialize)]
+struct ThreadList {
+    threads: Vec<RawChannel>,
+    #[serde(default)]
+    has_more: bool,
+}
+
+impl RawMessage {
+    fn into_message(self) -> Option<NewMessage> {
+        let time = DateTime::parse_from_rfc3339(&self.timestamp).ok()?;
+        let edited_time = self
+            .edited_timestamp
+            .and_then(|edited| DateTime::parse_from_rfc3339(&edited).ok());
+        let (reply_to, reply_to_author) = if self.kind == REPLY_MESSAGE_TYPE {
+            (
+                self.message_reference
+                    .and_then(|reference| reference.message_id),
+                self.referenced_message
+                    .map(|referenced| referenced.author.id),
+            )
+        } else {
+            (None, None)
+        };
+
+        Some(NewMessage {
+            message_id: self.id,
+            channel_id: self.channel_id,
+            // Not included in REST responses
+            guild_id: None,
+            parent_channel_id: None,
+            author_id: self.author.id,
+            author_name: self.author.username,
+            time: time.timestamp(),
+            edited_time: edited_time.map(|edited| edited.timestamp()),
+            content: self.content,
+            mentions: self
+                .mentions
+                .into_iter()
+                .map(|user| (user.id, user.username))
+                .collect(),
+            mention_roles: self.mention_roles,
+            reply_to,
+            reply_to_author,
+        })
+    }
+}
+
+impl RawChannel {
+    fn into_thread(self) -> Option<Thread> {
+        if !THREAD_CHANNEL_TYPES.contains(&self.kind) {
+            return None;
+        }
+        Some(Thread {
+            id: self.id,
+            parent_id: self.parent_id?,
+            guild_id: self.guild_id,
+            name: self.name.unwrap_or_default(),
+        })
+    }
+}
+
+impl RestClient {
+    pub fn new(token: &str) -> RestClient {
+        RestClient {
+            client: Client::new(),
+            token: token.to_owned(),
+        }
+    }
+
+    fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, RestError> {
+        loop {
+            let mut resp = self
+                .client
+                .get(&format!("{}{}", API_BASE, path))
+                .header(AUTHORIZATION, self.token.as_str())
+                .send()?;
+
+            if resp.status() == StatusCode::TOO_MANY_REQUESTS {
+                let wait = header_seconds(resp.headers(), "retry-after").unwrap_or(1.0);
+                warn!("Rate limited by Discord, retrying in {:.1}s", wait);
+                thread::sleep(Duration::from_millis((wait * 1000.0) as u64));
+                continue;
+            }
+            if !resp.status().is_success() {
+                let body = resp.text().unwrap_or_default();
+                return Err(RestError::Status(resp.status().as_u16(), body));
+            }
+
+            let value = resp.json()?;
+            // Wait for the bucket to refill instead of running into the limit
+            if header_seconds(resp.headers(), "x-ratelimit-remaining") == Some(0.0) {
+                if let Some(wait) = header_seconds(resp.headers(), "x-ratelimit-reset-after") {
+                    thread::sleep(Duration::from_millis((wait * 1000.0) as u64));
+                }
+            }
+            return Ok(value);
+        }
+    }
+
+    /// A page of a channel's messages older than `before`, newest first
+    ///
+    /// The messages have no guild, callers set it from the channel they asked for.
+    pub fn messages(
+        &self,
+        channel_id: ChannelId,
+        before: Option<MessageId>,
+        limit: u64,
+    ) -> Result<Vec<NewMessage>, RestError> {
+        let mut path = format!("/channels/{}/messages?limit={}", channel_id.0, limit);
+        if let Some(before) = before {
+            path += &format!("&before={}", before.0);
+        }
+
+        let messages: Vec<RawMessage> = self.get(&path)?;
+        Ok(messages
+            .into_iter()
+            .filter_map(RawMessage::into_message)
+            .collect())
+    }
+
+    /// A page of a channel's messages newer than `after`, oldest first
+    pub fn messages_after(
+        &self,
+        channel_id: ChannelId,
+        after: MessageId,
+        limit: u64,
+    ) -> Result<Vec<NewMessage>, RestError> {
+        let path = format!(
+            "/channels/{}/messages?limit={}&after={}",
+            channel_id.0, limit, after.0
+        );
+
+        let messages: Vec<RawMessage> = self.get(&path)?;
+        let mut messages = messages
+            .into_iter()
+            .filter_map(RawMessage::into_message)
+            .collect::<Vec<_>>();
+        messages.sort_by_key(|msg| msg.message_id);
+        Ok(messages)
+    }
+
+    /// The text, news and forum channels of a guild
+    pub fn text_channels(&self, guild_id: GuildId) -> Result<Vec<TextChannel>, RestError> {
+        let channels: Vec<RawChannel> = self.get(&format!("/guilds/{}/channels", guild_id.0))?;
+        Ok(channels
+            .into_iter()
+            .filter(|channel| TEXT_CHANNEL_TYPES.contains(&channel.kind))
+            .map(|channel| TextChannel {
+                id: channel.id,
+                name: channel.name.unwrap_or_default(),
+                position: channel.position,
+                forum: channel.kind == FORUM_CHANNEL_TYPE,
+            })
+            .collect())
+    }
+
+    /// The thread with this id, `None` if the channel is not a thread
+    pub fn thread(&self, channel_id: ChannelId) -> Result<Option<Thread>, RestError> {
+        let channel: RawChannel = self.get(&format!("/channels/{}", channel_id.0))?;
+        Ok(channel.into_thread())
+    }
+
+    /// Threads of a guild that are not archived
+    pub fn active_threads(&self, guild_id: GuildId) -> Result<Vec<Thread>, RestError> {
+        let list: ThreadList = self.get(&format!("/guilds/{}/threads/active", guild_id.0))?;
+        Ok(list
+            .threads
+            .into_iter()
+            .filter_map(RawChannel::into_thread)
+            .collect())
+    }
+
+    /// Archived public threads of a channel and archived private ones the user is in
+    pub fn archived_threads(&self, channel_id: ChannelId) -> Result<Vec<Thread>, RestError> {
+        let mut threads = Vec::new();
+
+        // Public threads are paged by archive time
+        let mut before: Option<String> = None;
+        loop {
+            let mut path = format!(
+                "/channels/{}/threads/archived/public?limit={}",
+                channel_id.0, PAGE_SIZE
+            );
+            if let Some(ref before) = before {
+                path += &format!("&before={}", before);
+            }
+            let list: ThreadList = self.get(&path)?;
+            before = list
+                .threads
+                .last()
+                .and_then(|thread| thread.thread_metadata.as_ref())
+                .map(|metadata| metadata.archive_timestamp.clone());
+            let has_more = list.has_more && before.is_some();
+            threads.extend(list.threads.into_iter().filter_map(RawChannel::into_thread));
+            if !has_more {
+                break;
+            }
+        }
+
+        // Joined private threads are paged by id
+        let mut before: Option<ChannelId> = None;
+        loop {
+            let mut path = format!(
+                "/channels/{}/users/@me/threads/archived/private?limit={}",
+                channel_id.0, PAGE_SIZE
+            );
+            if let Some(before) = before {
+                path += &format!("&before={}", before.0);
+            }
+            let list: ThreadList = self.get(&path)?;
+            before = list.threads.last().map(|thread| thread.id);
+            let has_more = list.has_more && before.is_some();
+            threads.extend(list.threads.into_iter().filter_map(RawChannel::into_thread));
+            if !has_more {
+                break;
+            }
+        }
+
+        Ok(threads)
+    }
+}
+
+fn header_seconds(headers: &HeaderMap, name: &str) -> Option<f64> {
+    headers.get(name)?.to_str().ok()?.parse().ok()
+}
@@ -1,9 +1,14 @@
+use std::fmt;
 use toml::de::Error as TomlDeserializeError;
 
 #[derive(Debug)]
 pub enum StoreError {
     Sqlite(rusqlite::Error),
     JsonError(serde_json::Error),
+    /// The transaction a write was batched into could not be committed
+    Commit(String),
+    /// The database writer thread exited
+    WriterStopped,
 }
 
 impl From<rusqlite::Error> for StoreError {
@@ -18,9 +23,19 @@ impl From<serde_json::Error> for StoreError {
     }
 }
 
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoreError::Sqlite(e) => write!(f, "database error: {}", e),
+            StoreError::JsonError(e) => write!(f, "invalid stored data: {}", e),
+            StoreError::Commit(e) => write!(f, "unable to commit write: {}", e),
+            StoreError::WriterStopped => write!(f, "database writer stopped"),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum ConfigError {
-    NoHome,
     NoParent,
 
     InvalidGuildFormat,
@@ -41,3 +56,110 @@ impl From<std::io::Error> for ConfigError {
         ConfigError::Io(e)
     }
 }
+
+#[derive(Debug)]
+pub enum AuthError {
+    /// No or invalid credentials were sent
+    Unauthorized,
+    /// The credentials do not allow this request
+    Forbidden,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthError::Unauthorized => write!(f, "missing or invalid credentials"),
+            AuthError::Forbidden => write!(f, "credentials do not permit this request"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// Errors returned by the web API
+#[derive(Debug)]
+pub enum ApiError {
+    Store(StoreError),
+    Serialization(serde_json::Error),
+    /// Invalid query parameters
+    BadRequest(String),
+    Auth(AuthError),
+    Internal(String),
+}
+
+impl From<StoreError> for ApiError {
+    fn from(e: StoreError) -> Self {
+        ApiError::Store(e)
+    }
+}
+
+impl From<AuthError> for ApiError {
+    fn from(e: AuthError) -> Self {
+        ApiError::Auth(e)
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ApiError::Store(e) => write!(f, "{}", e),
+            ApiError::Serialization(e) => write!(f, "unable to serialize response: {}", e),
+            ApiError::BadRequest(e) => write!(f, "{}", e),
+            ApiError::Auth(e) => write!(f, "{}", e),
+            ApiError::Internal(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// Errors of requests sent to Discord's REST API directly
+#[derive(Debug)]
+pub enum RestError {
+    Http(reqwest::Error),
+    /// A response with an unexpected status and its body
+    Status(u16, String),
+}
+
+impl From<reqwest::Error> for RestError {
+    fn from(e: reqwest::Error) -> Self {
+        RestError::Http(e)
+    }
+}
+
+impl fmt::Display for RestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RestError::Http(e) => write!(f, "request to Discord failed: {}", e),
+            RestError::Status(status, body) => {
+                write!(f, "Discord responded with {}: {}", status, body)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RestError {}
+
+#[derive(Debug)]
+pub enum ReportError {
+    /// The first day of the year or of the next one can not be represented
+    YearOutOfRange(i32),
+    Store(StoreError),
+}
+
+impl From<StoreError> for ReportError {
+    fn from(e: StoreError) -> Self {
+        ReportError::Store(e)
+    }
+}
+
+impl fmt::Display for ReportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReportError::YearOutOfRange(year) => write!(f, "year is out of range: {}", year),
+            ReportError::Store(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReportError {}
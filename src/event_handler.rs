@@ -1,25 +1,80 @@
+use log::{error, info, warn};
+use serenity::client::bridge::gateway::event::ShardStageUpdateEvent;
+use serenity::gateway::ConnectionStage;
 use serenity::{model::prelude::*, prelude::*};
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
 use std::sync::mpsc::{Receiver, Sender};
 use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
-use crate::store::StatsStore;
+use crate::discord::{RestClient, PAGE_SIZE};
+use crate::events::{EventBus, LiveEvent};
+use crate::metrics::METRICS;
+use crate::shutdown;
+use crate::store::{NewMessage, StatsStore};
+
+/// Seconds between looking up the reply targets of recorded messages
+const REPLY_LOOKUP_INTERVAL: u64 = 60;
 
 pub struct Handler {
     store: Arc<StatsStore>,
+    events: Arc<EventBus>,
+    rest: Arc<RestClient>,
     user: Mutex<RefCell<Option<User>>>,
     additional_channels: Vec<(Option<GuildId>, ChannelId)>,
+    /// Parents of the guild channels missing from the cache, `None` if they are not threads
+    parents: Mutex<HashMap<ChannelId, Option<ChannelId>>>,
+    replies: Arc<ReplyResolver>,
 }
 
 impl Handler {
     pub fn new(
         store: Arc<StatsStore>,
+        events: Arc<EventBus>,
+        rest: Arc<RestClient>,
         additional_channels: Vec<(Option<GuildId>, ChannelId)>,
     ) -> Handler {
         Handler {
+            replies: ReplyResolver::start(Arc::clone(&store), Arc::clone(&rest)),
             store,
+            events,
+            rest,
             user: Mutex::new(RefCell::new(None)),
             additional_channels,
+            parents: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The channel a thread or forum post belongs to
+    ///
+    /// Threads are not in the cache of the Discord library, so guild channels missing from it
+    /// are looked up once.
+    fn parent_channel(
+        &self,
+        ctx: &Context,
+        guild_id: Option<GuildId>,
+        channel_id: ChannelId,
+    ) -> Option<ChannelId> {
+        if guild_id.is_none() || ctx.cache.read().guild_channel(channel_id).is_some() {
+            return None;
+        }
+        if let Some(&parent) = self.parents.lock().get(&channel_id) {
+            return parent;
+        }
+
+        match self.rest.thread(channel_id) {
+            Ok(thread) => {
+                let parent = thread.map(|thread| thread.parent_id);
+                self.parents.lock().insert(channel_id, parent);
+                parent
+            }
+            Err(e) => {
+                warn!("Unable to look up channel {}: {}", channel_id.0, e);
+                None
+            }
         }
     }
 
@@ -29,6 +84,10 @@ impl Handler {
         guild_id: Option<GuildId>,
         channel_id: ChannelId,
     ) -> bool {
+        // Events arriving while the store is being closed are dropped
+        if shutdown::requested() {
+            return false;
+        }
         if let Some(ref current_user) = *self.user.lock().borrow() {
             if current_user.id == user_id
                 || self.additional_channels.contains(&(guild_id, channel_id))
@@ -51,22 +110,53 @@ impl Handler {
 }
 
 impl EventHandler for Handler {
-    fn message(&self, _ctx: Context, m: Message) {
-        if self.should_handle(m.author.id, m.guild_id, m.channel_id) {
-            if let Err(e) = self.store.insert_msg(&m) {
-                eprintln!("Error occured inserting message: {:?}", e)
+    fn message(&self, ctx: Context, m: Message) {
+        METRICS.mark_event();
+        // Messages in threads count for the channel the thread belongs to
+        let parent = self.parent_channel(&ctx, m.guild_id, m.channel_id);
+        if self.should_handle(m.author.id, m.guild_id, parent.unwrap_or(m.channel_id)) {
+            let mut msg = NewMessage::from(&m);
+            msg.parent_channel_id = parent;
+            match self.store.insert_msg(&msg) {
+                Ok(_) => {
+                    METRICS.messages_recorded.inc();
+                    self.replies.add(m.channel_id, m.id);
+                    self.events.publish(LiveEvent::message(
+                        m.id,
+                        m.channel_id,
+                        m.guild_id,
+                        m.author.id,
+                        m.timestamp.timestamp(),
+                    ))
+                }
+                Err(e) => {
+                    METRICS.insert_errors.inc();
+                    error!("Error occured inserting message: {:?}", e)
+                }
             }
         }
     }
 
     fn message_delete(&self, _ctx: Context, channel_id: ChannelId, message_id: MessageId) {
+        METRICS.mark_event();
         if let Ok(msg) = self
             .store
             .get_message_with_channel_id(channel_id, message_id)
         {
-            if self.should_handle(msg.author_id, msg.guild_id, msg.channel_id) {
-                if let Err(e) = self.store.insert_deletion(channel_id, message_id) {
-                    eprintln!("Error occured inserting deletion: {:?}", e)
+            if self.should_handle(msg.author_id, msg.guild_id, msg.channel()) {
+                match self.store.insert_deletion(channel_id, message_id) {
+                    Ok(()) => {
+                        METRICS.deletions_recorded.inc();
+                        self.events.publish(LiveEvent::deletion(
+                            message_id,
+                            channel_id,
+                            msg.guild_id,
+                        ))
+                    }
+                    Err(e) => {
+                        METRICS.insert_errors.inc();
+                        error!("Error occured inserting deletion: {:?}", e)
+                    }
                 }
             }
         }
@@ -78,14 +168,26 @@ impl EventHandler for Handler {
         channel_id: ChannelId,
         message_ids: Vec<MessageId>,
     ) {
+        METRICS.mark_event();
         for message_id in message_ids {
             if let Ok(msg) = self
                 .store
                 .get_message_with_channel_id(channel_id, message_id)
             {
-                if self.should_handle(msg.author_id, msg.guild_id, msg.channel_id) {
-                    if let Err(e) = self.store.insert_deletion(channel_id, message_id) {
-                        eprintln!("Error occured inserting deletion: {:?}", e)
+                if self.should_handle(msg.author_id, msg.guild_id, msg.channel()) {
+                    match self.store.insert_deletion(channel_id, message_id) {
+                        Ok(()) => {
+                            METRICS.deletions_recorded.inc();
+                            self.events.publish(LiveEvent::deletion(
+                                message_id,
+                                channel_id,
+                                msg.guild_id,
+                            ))
+                        }
+                        Err(e) => {
+                            METRICS.insert_errors.inc();
+                            error!("Error occured inserting deletion: {:?}", e)
+                        }
                     }
                 }
             }
@@ -94,29 +196,124 @@ impl EventHandler for Handler {
 
     fn message_update(
         &self,
-        _ctx: Context,
+        ctx: Context,
         old: Option<Message>,
         new: Option<Message>,
         update: MessageUpdateEvent,
     ) {
+        METRICS.mark_event();
         if let Some(ref author) = update.author {
             let msg = new.or(old);
             let guild = msg.as_ref().and_then(|msg| msg.guild_id);
-            if self.should_handle(author.id, guild, update.channel_id) {
-                if let Err(e) = self.store.insert_edit(&update) {
-                    eprintln!("Error occured inserting deletion: {:?}", e)
+            let parent = self.parent_channel(&ctx, guild, update.channel_id);
+            if self.should_handle(author.id, guild, parent.unwrap_or(update.channel_id)) {
+                match self.store.insert_edit(&update) {
+                    Ok(true) => {
+                        METRICS.edits_recorded.inc();
+                        self.events
+                            .publish(LiveEvent::edit(update.id, update.channel_id, guild))
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        METRICS.insert_errors.inc();
+                        error!("Error occured inserting edit: {:?}", e)
+                    }
                 }
             }
         }
     }
 
     fn ready(&self, ctx: Context, ready: Ready) {
-        println!("{} is connected", ready.user.name);
-        self.store.set_current_user(ready.user.id);
+        info!("{} is connected", ready.user.name);
+        METRICS.mark_event();
+        METRICS.gateway_connected.store(true, Ordering::Relaxed);
+        // A second ready means the session was re-established
+        if self.user.lock().borrow().is_some() {
+            METRICS.gateway_reconnects.inc();
+        }
+        if let Err(e) = self.store.set_current_user(ready.user.id, &ready.user.name) {
+            error!("Error occured saving current user: {:?}", e)
+        }
 
         *self.user.lock().borrow_mut() = Some(ready.user.into());
         ctx.set_presence(None, serenity::model::user::OnlineStatus::Offline);
     }
+
+    fn resume(&self, _ctx: Context, _resumed: ResumedEvent) {
+        METRICS.mark_event();
+        METRICS.gateway_connected.store(true, Ordering::Relaxed);
+        METRICS.gateway_reconnects.inc();
+    }
+
+    fn shard_stage_update(&self, _ctx: Context, update: ShardStageUpdateEvent) {
+        METRICS
+            .gateway_connected
+            .store(update.new == ConnectionStage::Connected, Ordering::Relaxed);
+    }
+}
+
+/// Looks up which messages recorded live reply to
+///
+/// The gateway model of the Discord library has no reply targets, so recorded messages are
+/// fetched again in batches per channel.
+struct ReplyResolver {
+    store: Arc<StatsStore>,
+    rest: Arc<RestClient>,
+    /// Oldest and newest recorded message of each channel
+    pending: Mutex<HashMap<ChannelId, (MessageId, MessageId)>>,
+}
+
+impl ReplyResolver {
+    /// Looks up the pending messages every `REPLY_LOOKUP_INTERVAL` seconds
+    fn start(store: Arc<StatsStore>, rest: Arc<RestClient>) -> Arc<ReplyResolver> {
+        let resolver = Arc::new(ReplyResolver {
+            store,
+            rest,
+            pending: Mutex::new(HashMap::new()),
+        });
+        let background = Arc::clone(&resolver);
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(REPLY_LOOKUP_INTERVAL));
+            // Messages still pending get their targets from the next `fetch-history`
+            if shutdown::requested() {
+                return;
+            }
+            background.resolve();
+        });
+        resolver
+    }
+
+    fn add(&self, channel_id: ChannelId, message_id: MessageId) {
+        let mut pending = self.pending.lock();
+        let range = pending
+            .entry(channel_id)
+            .or_insert((message_id, message_id));
+        range.0 = range.0.min(message_id);
+        range.1 = range.1.max(message_id);
+    }
+
+    fn resolve(&self) {
+        let pending = std::mem::replace(&mut *self.pending.lock(), HashMap::new());
+        for (channel_id, (oldest, newest)) in pending {
+            let mut after = MessageId(oldest.0 - 1);
+            while after < newest {
+                let msgs = match self.rest.messages_after(channel_id, after, PAGE_SIZE) {
+                    Ok(msgs) => msgs,
+                    Err(e) => {
+                        warn!("Unable to look up replies in {}: {}", channel_id.0, e);
+                        break;
+                    }
+                };
+                after = match msgs.last() {
+                    Some(msg) => msg.message_id,
+                    None => break,
+                };
+                if let Err(e) = self.store.set_reply_targets(&msgs) {
+                    error!("Error occured saving reply targets: {:?}", e)
+                }
+            }
+        }
+    }
 }
 
 pub struct OneshotData {
@@ -124,6 +321,31 @@ pub struct OneshotData {
     pub ready: Ready,
 }
 
+impl OneshotData {
+    /// Ids and names of the guilds the user is in
+    pub fn guilds(&self) -> Vec<(GuildId, String)> {
+        self.ready
+            .guilds
+            .iter()
+            .map(|guild| {
+                use serenity::model::guild::GuildStatus::*;
+                match guild {
+                    OnlinePartialGuild(g) => (g.id, g.name.clone()),
+                    OnlineGuild(g) => (g.id, g.name.clone()),
+                    Offline(g) => (
+                        g.id,
+                        g.id.to_partial_guild(&self.context.http)
+                            .expect("Unable to fetch guild data")
+                            .name
+                            .clone(),
+                    ),
+                    _ => panic!("Unknown guild state"),
+                }
+            })
+            .collect()
+    }
+}
+
 pub struct OneshotHandler {
     tx: Arc<Mutex<Sender<OneshotData>>>,
 }
@@ -142,8 +364,14 @@ impl EventHandler for OneshotHandler {
         {
             let mut ctx_lock = context.cache.write();
             for (&c_id, ch) in &ready.private_channels {
-                if let Some(private) = ch.clone().private() {
-                    ctx_lock.private_channels.insert(c_id, private);
+                match ch {
+                    Channel::Private(private) => {
+                        ctx_lock.private_channels.insert(c_id, Arc::clone(private));
+                    }
+                    Channel::Group(group) => {
+                        ctx_lock.groups.insert(c_id, Arc::clone(group));
+                    }
+                    _ => {}
                 }
             }
         }
@@ -0,0 +1,115 @@
+use serde_derive::Serialize;
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use serenity::prelude::Mutex;
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// A change to the store, sent to the web server as it happens
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LiveEvent {
+    Message {
+        message_id: String,
+        channel_id: String,
+        guild_id: Option<String>,
+        author_id: String,
+        time: i64,
+    },
+    Edit {
+        message_id: String,
+        channel_id: String,
+        guild_id: Option<String>,
+    },
+    Deletion {
+        message_id: String,
+        channel_id: String,
+        guild_id: Option<String>,
+    },
+}
+
+impl LiveEvent {
+    pub fn message(
+        message_id: MessageId,
+        channel_id: ChannelId,
+        guild_id: Option<GuildId>,
+        author_id: UserId,
+        time: i64,
+    ) -> LiveEvent {
+        LiveEvent::Message {
+            message_id: message_id.0.to_string(),
+            channel_id: channel_id.0.to_string(),
+            guild_id: guild_id.map(|g| g.0.to_string()),
+            author_id: author_id.0.to_string(),
+            time,
+        }
+    }
+
+    pub fn edit(
+        message_id: MessageId,
+        channel_id: ChannelId,
+        guild_id: Option<GuildId>,
+    ) -> LiveEvent {
+        LiveEvent::Edit {
+            message_id: message_id.0.to_string(),
+            channel_id: channel_id.0.to_string(),
+            guild_id: guild_id.map(|g| g.0.to_string()),
+        }
+    }
+
+    pub fn deletion(
+        message_id: MessageId,
+        channel_id: ChannelId,
+        guild_id: Option<GuildId>,
+    ) -> LiveEvent {
+        LiveEvent::Deletion {
+            message_id: message_id.0.to_string(),
+            channel_id: channel_id.0.to_string(),
+            guild_id: guild_id.map(|g| g.0.to_string()),
+        }
+    }
+
+    /// Event name used in the event stream
+    pub fn name(&self) -> &'static str {
+        match self {
+            LiveEvent::Message { .. } => "message",
+            LiveEvent::Edit { .. } => "edit",
+            LiveEvent::Deletion { .. } => "deletion",
+        }
+    }
+
+    pub fn channel_id(&self) -> &str {
+        match self {
+            LiveEvent::Message { channel_id, .. }
+            | LiveEvent::Edit { channel_id, .. }
+            | LiveEvent::Deletion { channel_id, .. } => channel_id,
+        }
+    }
+
+    pub fn guild_id(&self) -> Option<&str> {
+        match self {
+            LiveEvent::Message { guild_id, .. }
+            | LiveEvent::Edit { guild_id, .. }
+            | LiveEvent::Deletion { guild_id, .. } => guild_id.as_ref().map(String::as_str),
+        }
+    }
+}
+
+/// Fans out events from the Discord event handler to every subscriber
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<Sender<LiveEvent>>>,
+}
+
+impl EventBus {
+    pub fn subscribe(&self) -> Receiver<LiveEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.subscribers.lock().push(tx);
+        rx
+    }
+
+    /// Sends an event to all subscribers, dropping those that went away
+    pub fn publish(&self, event: LiveEvent) {
+        self.subscribers
+            .lock()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
@@ -0,0 +1,189 @@
+use serde_derive::Serialize;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::store::{GraphUser, InteractionGraph};
+
+/// File formats the interaction graph can be exported in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphFormat {
+    /// Graphviz
+    Dot,
+    /// GraphML, read by Gephi and most other graph tools
+    Graphml,
+    Json,
+}
+
+impl GraphFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            GraphFormat::Dot => "text/vnd.graphviz",
+            GraphFormat::Graphml => "application/graphml+xml",
+            GraphFormat::Json => "application/json",
+        }
+    }
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "graphml" => Ok(GraphFormat::Graphml),
+            "json" => Ok(GraphFormat::Json),
+            _ => Err(format!(
+                "unknown graph format {}, expected dot, graphml or json",
+                s
+            )),
+        }
+    }
+}
+
+/// The graph as served by the API, ids are strings as they do not fit in a javascript number
+#[derive(Serialize)]
+pub struct GraphDocument {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+#[derive(Serialize)]
+pub struct GraphNode {
+    pub id: String,
+    pub name: Option<String>,
+    pub messages: i64,
+}
+
+/// Messages from `source` addressed to `target`, `weight` is the sum of both kinds
+#[derive(Serialize)]
+pub struct GraphEdge {
+    pub source: String,
+    pub target: String,
+    pub weight: i64,
+    pub mentions: i64,
+    pub replies: i64,
+}
+
+impl From<&InteractionGraph> for GraphDocument {
+    fn from(graph: &InteractionGraph) -> Self {
+        GraphDocument {
+            nodes: graph
+                .users
+                .iter()
+                .map(|user| GraphNode {
+                    id: user.user_id.0.to_string(),
+                    name: user.name.clone(),
+                    messages: user.messages,
+                })
+                .collect(),
+            edges: graph
+                .interactions
+                .iter()
+                .map(|edge| GraphEdge {
+                    source: edge.source.0.to_string(),
+                    target: edge.target.0.to_string(),
+                    weight: edge.weight(),
+                    mentions: edge.mentions,
+                    replies: edge.replies,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Writes the graph in the given format
+pub fn render(graph: &InteractionGraph, format: GraphFormat) -> Result<String, serde_json::Error> {
+    match format {
+        GraphFormat::Dot => Ok(render_dot(graph)),
+        GraphFormat::Graphml => Ok(render_graphml(graph)),
+        GraphFormat::Json => serde_json::to_string_pretty(&GraphDocument::from(graph)),
+    }
+}
+
+fn render_dot(graph: &InteractionGraph) -> String {
+    let mut out = String::from("digraph interactions {\n");
+    for user in &graph.users {
+        let _ = writeln!(
+            out,
+            "    \"{}\" [label=\"{}\", messages={}];",
+            user.user_id.0,
+            dot_escape(&label(user)),
+            user.messages
+        );
+    }
+    for edge in &graph.interactions {
+        let _ = writeln!(
+            out,
+            "    \"{}\" -> \"{}\" [weight={}, mentions={}, replies={}];",
+            edge.source.0,
+            edge.target.0,
+            edge.weight(),
+            edge.mentions,
+            edge.replies
+        );
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn render_graphml(graph: &InteractionGraph) -> String {
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n  \
+         <key id=\"name\" for=\"node\" attr.name=\"name\" attr.type=\"string\"/>\n  \
+         <key id=\"messages\" for=\"node\" attr.name=\"messages\" attr.type=\"long\"/>\n  \
+         <key id=\"weight\" for=\"edge\" attr.name=\"weight\" attr.type=\"long\"/>\n  \
+         <key id=\"mentions\" for=\"edge\" attr.name=\"mentions\" attr.type=\"long\"/>\n  \
+         <key id=\"replies\" for=\"edge\" attr.name=\"replies\" attr.type=\"long\"/>\n  \
+         <graph id=\"interactions\" edgedefault=\"directed\">\n",
+    );
+    for user in &graph.users {
+        let _ = write!(
+            out,
+            "    <node id=\"{}\">\n      \
+             <data key=\"name\">{}</data>\n      \
+             <data key=\"messages\">{}</data>\n    \
+             </node>\n",
+            user.user_id.0,
+            xml_escape(&label(user)),
+            user.messages
+        );
+    }
+    for edge in &graph.interactions {
+        let _ = write!(
+            out,
+            "    <edge source=\"{}\" target=\"{}\">\n      \
+             <data key=\"weight\">{}</data>\n      \
+             <data key=\"mentions\">{}</data>\n      \
+             <data key=\"replies\">{}</data>\n    \
+             </edge>\n",
+            edge.source.0,
+            edge.target.0,
+            edge.weight(),
+            edge.mentions,
+            edge.replies
+        );
+    }
+    out.push_str("  </graph>\n</graphml>\n");
+    out
+}
+
+/// The user's name, or the id for users whose name was never recorded
+fn label(user: &GraphUser) -> String {
+    user.name
+        .clone()
+        .unwrap_or_else(|| user.user_id.0.to_string())
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', " ")
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
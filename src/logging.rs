@@ -0,0 +1,153 @@
+use log::{LevelFilter, Log, Metadata, Record};
+use serde_derive::{Deserialize, Serialize};
+use serenity::prelude::Mutex;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::config::LogConfig;
+
+/// Environment variable that overrides the configured filter
+pub const LOG_ENV_VAR: &str = "DISCORD_STATS_LOG";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Plain,
+    Json,
+}
+
+/// A level for all modules under a path, or every module if there is none
+struct Directive {
+    module: Option<String>,
+    level: LevelFilter,
+}
+
+pub struct Logger {
+    /// Sorted so the most specific directive comes first
+    directives: Vec<Directive>,
+    format: LogFormat,
+    output: Mutex<Box<dyn Write + Send>>,
+}
+
+/// Installs the logger, writing to `config.file` or stderr
+pub fn init(config: &LogConfig) -> io::Result<()> {
+    let filter = std::env::var(LOG_ENV_VAR).unwrap_or_else(|_| config.level.clone());
+
+    let output: Box<dyn Write + Send> = match config.file {
+        Some(ref path) => Box::new(open_log_file(path)?),
+        None => Box::new(io::stderr()),
+    };
+
+    let logger = Logger {
+        directives: parse_filter(&filter),
+        format: config.format,
+        output: Mutex::new(output),
+    };
+    log::set_max_level(logger.max_level());
+    log::set_boxed_logger(Box::new(logger)).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
+
+fn open_log_file(path: &Path) -> io::Result<std::fs::File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Parses filters like `info` or `warn,discord_statistics::scan=debug`
+///
+/// Invalid levels are ignored.
+fn parse_filter(filter: &str) -> Vec<Directive> {
+    let mut directives: Vec<Directive> = filter
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .filter_map(|part| {
+            let mut split = part.splitn(2, '=');
+            let first = split.next()?;
+            match split.next() {
+                Some(level) => Some(Directive {
+                    module: Some(first.to_owned()),
+                    level: level.parse().ok()?,
+                }),
+                None => match first.parse() {
+                    Ok(level) => Some(Directive {
+                        module: None,
+                        level,
+                    }),
+                    // A bare module name enables everything for it
+                    Err(_) => Some(Directive {
+                        module: Some(first.to_owned()),
+                        level: LevelFilter::Trace,
+                    }),
+                },
+            }
+        })
+        .collect();
+
+    directives.sort_by_key(|d| std::cmp::Reverse(d.module.as_ref().map_or(0, String::len)));
+    directives
+}
+
+impl Logger {
+    fn level_for(&self, target: &str) -> LevelFilter {
+        self.directives
+            .iter()
+            .find(|d| match d.module {
+                Some(ref module) => {
+                    target == module || target.starts_with(&format!("{}::", module))
+                }
+                None => true,
+            })
+            .map_or(LevelFilter::Error, |d| d.level)
+    }
+
+    fn max_level(&self) -> LevelFilter {
+        self.directives
+            .iter()
+            .map(|d| d.level)
+            .max()
+            .unwrap_or(LevelFilter::Error)
+    }
+
+    fn format(&self, record: &Record) -> String {
+        let time = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        match self.format {
+            LogFormat::Plain => format!(
+                "{} {:<5} {}: {}",
+                time,
+                record.level(),
+                record.target(),
+                record.args()
+            ),
+            LogFormat::Json => serde_json::json!({
+                "time": time,
+                "level": record.level().to_string(),
+                "target": record.target(),
+                "message": record.args().to_string(),
+            })
+            .to_string(),
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level_for(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = self.format(record);
+        // There is nowhere left to report a failure to log
+        let _ = writeln!(self.output.lock(), "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = self.output.lock().flush();
+    }
+}
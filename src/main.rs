@@ -1,135 +1,99 @@
-use iron::{Chain, Iron};
-use persistent::Read;
-use router::router;
-use serde_derive::{Deserialize, Serialize};
-use serenity::model::id::{ChannelId, GuildId};
+use chrono::Datelike;
+use console::Term;
+use iron::Listening;
+use log::{error, info};
+use serenity::model::channel::Channel;
+use serenity::model::id::{ChannelId, GuildId, MessageId};
 use serenity::prelude::*;
 use std::collections::HashSet;
+use std::ffi::OsStr;
 use std::fs::DirBuilder;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
 use std::sync::Arc;
 use std::thread;
 
+mod connection;
 mod store;
-use store::StatsStore;
+use store::{Filter, StatsStore};
 
+mod analysis;
+mod graph;
+use graph::GraphFormat;
+
+mod picker;
+mod report;
 mod scan;
+mod summary;
 
 mod api;
+mod auth;
+mod openapi;
+mod web;
+
+mod discord;
+use discord::RestClient;
+
 mod event_handler;
 use event_handler::OneshotData;
 
-mod error;
-use error::ConfigError;
+mod events;
+use events::EventBus;
 
-#[derive(Serialize, Deserialize)]
-struct Config {
-    discord_token: String,
-    tracked_channels: Vec<String>,
-}
+mod metrics;
+use metrics::METRICS;
 
-impl Default for Config {
-    fn default() -> Self {
-        Config {
-            discord_token: String::new(),
-            tracked_channels: Vec::new(),
-        }
-    }
-}
+mod config;
+use config::Config;
 
-impl Config {
-    pub fn load() -> Result<Config, ConfigError> {
-        let config_path = Config::config_path().ok_or(ConfigError::NoHome)?;
-
-        Ok(if !config_path.exists() {
-            DirBuilder::new()
-                .recursive(true)
-                .create(config_path.parent().ok_or(ConfigError::NoParent)?)?;
-            let conf = Config::default();
-            std::fs::write(
-                &config_path,
-                toml::to_string(&conf).expect("configuration is serializable"),
-            )?;
-            conf
-        } else {
-            let config_str = std::fs::read_to_string(config_path)?;
-            toml::from_str(&config_str)?
-        })
-    }
-
-    pub fn save(&self) -> Result<(), ConfigError> {
-        let config_path = Config::config_path().ok_or(ConfigError::NoHome)?;
-
-        std::fs::write(
-            config_path,
-            toml::to_string(self).expect("configuration is serializable"),
-        )?;
-        Ok(())
-    }
-
-    pub fn tracked_channels(&self) -> Result<Vec<(Option<GuildId>, ChannelId)>, ConfigError> {
-        let mut out = Vec::new();
-        for channel in &self.tracked_channels {
-            let chan = if channel.contains('|') {
-                let mut split_item = channel.split('|');
-                let guild = split_item.next().ok_or(ConfigError::InvalidGuildFormat)?;
-                let channel = split_item.next().ok_or(ConfigError::InvalidChannelFormat)?;
-                (
-                    Some(GuildId(
-                        guild
-                            .parse::<u64>()
-                            .map_err(|_| ConfigError::InvalidChannelFormat)?,
-                    )),
-                    channel
-                        .parse()
-                        .map_err(|_| ConfigError::InvalidChannelFormat)?,
-                )
-            } else {
-                (
-                    None,
-                    channel
-                        .parse()
-                        .map_err(|_| ConfigError::InvalidChannelFormat)?,
-                )
-            };
-            out.push(chan);
-        }
-        Ok(out)
-    }
-
-    pub fn config_path() -> Option<std::path::PathBuf> {
-        Config::data_root().map(|h| h.join("config.toml"))
-    }
-
-    #[cfg(target_os = "macos")]
-    pub fn data_root() -> Option<std::path::PathBuf> {
-        dirs::home_dir().map(|h| h.join(".config/discord-statistics/"))
-    }
-
-    #[cfg(not(target_os = "macos"))]
-    pub fn data_root() -> Option<std::path::PathBuf> {
-        dirs::config_dir().map(|h| h.join("discord-statistics/"))
-    }
-}
+mod error;
+mod logging;
+mod shutdown;
+mod time;
+use time::Bound;
 
 fn main() {
-    let mut config = match Config::load() {
-        Ok(config) => config,
-        Err(e) => {
-            eprintln!("Error loading configuration:\n{:?}", e);
-            std::process::exit(1)
-        }
-    };
-    let db_path = match Config::data_root() {
-        Some(data) => data.join("store.sqlite3"),
-        None => {
-            eprintln!("Unable to get users config dir");
-            std::process::exit(2)
-        }
-    };
+    METRICS.mark_started();
 
     use clap::{App, Arg, SubCommand};
     let matches = App::new("Discord statistics")
         .author("Noskcaj19")
+        .arg(
+            Arg::with_name("config")
+                .help("Path to the configuration file")
+                .long("config")
+                .env("DISCORD_STATS_CONFIG")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("data-dir")
+                .help("Directory the message database is stored in")
+                .long("data-dir")
+                .env("DISCORD_STATS_DATA_DIR")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .help("Append logs to this file instead of stderr")
+                .long("log-file")
+                .env("DISCORD_STATS_LOG_FILE")
+                .takes_value(true)
+                .global(true),
+        )
+        .args(&web_args())
+        .arg(
+            Arg::with_name("no-web")
+                .help("Run the collector without the web server")
+                .long("no-web"),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Run only the web server against an existing database")
+                .args(&web_args()),
+        )
         .subcommand(
             SubCommand::with_name("token")
                 .about("Store your Discord token")
@@ -144,13 +108,26 @@ fn main() {
                 .about("Start tracking a channel")
                 .arg(
                     Arg::with_name("group-name")
-                        .required(true)
-                        .help("Guild name or private channel user"),
+                        .required_unless("interactive")
+                        .help("Guild name, private channel user, or group name or comma separated members"),
                 )
                 .arg(
                     Arg::with_name("channel-name")
                         .required(false)
                         .help("Channel name if guild is provided"),
+                )
+                .arg(
+                    Arg::with_name("interactive")
+                        .help("Pick channels from a searchable list")
+                        .long("interactive")
+                        .short("i")
+                        .conflicts_with("group-name"),
+                )
+                .arg(
+                    Arg::with_name("fetch-history")
+                        .help("Fetch the history of the picked channels right away")
+                        .long("fetch-history")
+                        .requires("interactive"),
                 ),
         )
         .subcommand(
@@ -158,15 +135,141 @@ fn main() {
                 .about("Add previously sent messages to the log")
                 .arg(
                     Arg::with_name("max-count")
-                        .help("Maximum amount of messages to search")
+                        .help("Maximum amount of messages to search, all in the range if a start is given")
                         .long("max-count")
-                        .default_value("500")
                         .takes_value(true)
                         .short("c"),
+                )
+                .arg(
+                    Arg::with_name("since")
+                        .help("Only fetch messages sent at or after this date or time")
+                        .long("since")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("until")
+                        .help("Only fetch messages sent before this time or up to this date")
+                        .long("until")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("jobs")
+                        .help("Number of channels to scan at the same time")
+                        .long("jobs")
+                        .default_value("4")
+                        .takes_value(true)
+                        .short("j"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("report")
+                .about("Write a year in review of the logged messages as HTML and Markdown")
+                .arg(
+                    Arg::with_name("year")
+                        .help("Year to summarize, the current year by default")
+                        .long("year")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .help("Directory to write the report files to")
+                        .long("output")
+                        .short("o")
+                        .default_value(".")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("reindex")
+                .about("Extract links from messages stored before links were recorded"),
+        )
+        .subcommand(
+            SubCommand::with_name("stats")
+                .about("Print the dashboard's statistics in the terminal")
+                .arg(
+                    Arg::with_name("json")
+                        .help("Print JSON instead of tables")
+                        .long("json"),
+                )
+                .args(&filter_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("export-graph")
+                .about("Export who mentions and replies to whom from the database")
+                .arg(
+                    Arg::with_name("format")
+                        .help("dot, graphml or json")
+                        .long("format")
+                        .short("f")
+                        .default_value("dot")
+                        .takes_value(true),
+                )
+                .args(&filter_args())
+                .arg(
+                    Arg::with_name("output")
+                        .help("File to write to instead of standard output")
+                        .long("output")
+                        .short("o")
+                        .takes_value(true),
                 ),
         )
         .get_matches();
 
+    // Logging is configured by the config file, so errors until then are printed directly
+    let config_path = match global_value_of(&matches, "config")
+        .map(PathBuf::from)
+        .or_else(config::default_config_path)
+    {
+        Some(path) => path,
+        None => {
+            eprintln!("Unable to get users config dir");
+            std::process::exit(2)
+        }
+    };
+    let mut config = match Config::load(&config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error loading configuration:\n{:?}", e);
+            std::process::exit(1)
+        }
+    };
+
+    if let Some(log_file) = global_value_of(&matches, "log-file") {
+        config.log.file = Some(PathBuf::from(log_file));
+    }
+    if let Err(e) = logging::init(&config.log) {
+        eprintln!("Unable to set up logging:\n{:?}", e);
+        std::process::exit(1)
+    }
+
+    let data_dir = match global_value_of(&matches, "data-dir")
+        .map(PathBuf::from)
+        .or_else(config::default_data_dir)
+    {
+        Some(dir) => dir,
+        None => {
+            error!("Unable to get users data dir");
+            std::process::exit(2)
+        }
+    };
+    if let Err(e) = DirBuilder::new().recursive(true).create(&data_dir) {
+        error!("Unable to create data dir {}: {:?}", data_dir.display(), e);
+        std::process::exit(2)
+    }
+    let db_path = data_dir.join(config::DATABASE_FILE_NAME);
+    match config::migrate_legacy_database(&db_path) {
+        Ok(Some(old_path)) => info!(
+            "Moved database from {} to {}",
+            old_path.display(),
+            db_path.display()
+        ),
+        Ok(None) => {}
+        Err(e) => {
+            error!("Unable to move existing database:\n{:?}", e);
+            std::process::exit(2)
+        }
+    }
+
     if let Some(store_token) = matches.subcommand_matches("store-token") {
         let token = store_token
             .value_of("token")
@@ -174,7 +277,7 @@ fn main() {
         config.discord_token = token.to_owned();
 
         if let Err(e) = config.save() {
-            eprintln!("An error occured saving the configuration file:\n{:?}", e);
+            error!("An error occured saving the configuration file:\n{:?}", e);
         } else {
             println!("Successfully saved discord token");
         }
@@ -182,13 +285,125 @@ fn main() {
         return;
     }
 
+    if let Some(bind) = global_value_of(&matches, "bind") {
+        config.web.bind_address = bind.to_string_lossy().into_owned();
+    }
+    if let Some(port) = global_value_of(&matches, "port") {
+        config.web.port = match port.to_string_lossy().parse() {
+            Ok(port) => port,
+            Err(_) => {
+                error!("port must be an integer between 0 and 65535");
+                std::process::exit(2)
+            }
+        };
+    }
+    if let Some(base_path) = global_value_of(&matches, "base-path") {
+        config.web.base_path = base_path.to_string_lossy().into_owned();
+    }
+    if matches.is_present("no-web") {
+        config.web.enabled = false;
+    }
+
+    if matches.subcommand_matches("serve").is_some() {
+        if !db_path.exists() {
+            error!("No database found at {}", db_path.display());
+            std::process::exit(2)
+        }
+        let stats = match StatsStore::new(&db_path) {
+            Ok(conn) => Arc::new(conn),
+            Err(e) => {
+                error!("Unable to open database:\n{:?}", e);
+                std::process::exit(2)
+            }
+        };
+        // Nothing is collected, so the event stream only sends keepalives
+        let events = Arc::new(EventBus::default());
+        let listening = match web::serve(&config.web, &config.analysis, stats.clone(), events) {
+            Ok(listening) => listening,
+            Err(e) => {
+                error!("Unable to start web server: {:?}", e);
+                std::process::exit(2)
+            }
+        };
+
+        // `tx` stays alive here so this keeps serving if no handler could be installed
+        let (tx, rx) = mpsc::channel();
+        let signal = tx.clone();
+        shutdown::install(move || {
+            let _ = signal.send(());
+        });
+        let _ = rx.recv();
+        drop(tx);
+        shut_down(Some(listening), &stats);
+        return;
+    }
+
+    if matches.subcommand_matches("reindex").is_some() {
+        if !db_path.exists() {
+            error!("No database found at {}", db_path.display());
+            std::process::exit(2)
+        }
+        let stats = match StatsStore::new(&db_path) {
+            Ok(stats) => stats,
+            Err(e) => {
+                error!("Unable to open database:\n{:?}", e);
+                std::process::exit(2)
+            }
+        };
+        match stats.reindex_links() {
+            Ok(count) => println!("Added {} links", count),
+            Err(e) => {
+                error!("Unable to extract links:\n{:?}", e);
+                std::process::exit(2)
+            }
+        }
+        shut_down(None, &stats);
+        return;
+    }
+
+    if let Some(report) = matches.subcommand_matches("report") {
+        if let Err(e) = write_report(report, &db_path, &config.analysis.stopwords) {
+            error!("{}", e);
+            std::process::exit(2)
+        }
+        return;
+    }
+
+    if let Some(summary) = matches.subcommand_matches("stats") {
+        if let Err(e) = print_summary(summary, &db_path) {
+            error!("{}", e);
+            std::process::exit(2)
+        }
+        return;
+    }
+
+    if let Some(export) = matches.subcommand_matches("export-graph") {
+        if let Err(e) = export_graph(export, &db_path) {
+            error!("{}", e);
+            std::process::exit(2)
+        }
+        return;
+    }
+
     let token = std::env::var("DISCORD_TOKEN").unwrap_or(config.discord_token.clone());
     if token.is_empty() || serenity::client::validate_token(&token).is_err() {
-        eprintln!("Empty or invalid token, please set it by running `discord-statistics token $DISCORD_TOKEN`\nexiting");
+        error!("Empty or invalid token, please set it by running `discord-statistics token $DISCORD_TOKEN`\nexiting");
         return;
     }
+    let rest = Arc::new(RestClient::new(&token));
 
     if let Some(track) = matches.subcommand_matches("track") {
+        if track.is_present("interactive") {
+            track_interactive(
+                &mut config,
+                &token,
+                &rest,
+                &db_path,
+                track.is_present("fetch-history"),
+            );
+            return;
+        }
+
         // Name of the private channel user or guild
         let group_name = track
             .value_of("group-name")
@@ -197,7 +412,7 @@ fn main() {
         let data = get_oneshot_data(&token);
 
         let id_str = if let Some(channel_name) = track.value_of("channel-name") {
-            resolve_guild_channel_names(&data, group_name, channel_name)
+            resolve_guild_channel_names(&data, &rest, group_name, channel_name)
                 .map(|(gid, cid)| format!("{}|{}", gid.0, cid.0))
         } else {
             resolve_private_channel(&data, group_name).map(|id| id.0.to_string())
@@ -208,10 +423,10 @@ fn main() {
                 config.tracked_channels.push(id_str);
                 println!("Added channel to tracking list");
                 if let Err(e) = config.save() {
-                    eprintln!("An error occured saving the configuration file:\n{:?}", e);
+                    error!("An error occured saving the configuration file:\n{:?}", e);
                 }
             }
-            None => eprintln!("Unable to find a matching channel"),
+            None => error!("Unable to find a matching channel"),
         }
 
         return;
@@ -220,16 +435,41 @@ fn main() {
     let stats = match StatsStore::new(&db_path) {
         Ok(conn) => Arc::new(conn),
         Err(_) => {
-            eprintln!("Unable to construct tables. aborting");
+            error!("Unable to construct tables. aborting");
             std::process::exit(0);
         }
     };
 
     if let Some(fetch) = matches.subcommand_matches("fetch-history") {
-        let max_count: u64 = match fetch.value_of("max-count").unwrap_or("500").parse() {
-            Ok(c) => c,
-            Err(_) => {
-                eprintln!("max-count must be an integer");
+        let (since, until) = match (
+            snowflake_arg(fetch, "since", Bound::Start),
+            snowflake_arg(fetch, "until", Bound::End),
+        ) {
+            (Ok(since), Ok(until)) => (since, until),
+            (Err(name), _) | (_, Err(name)) => {
+                error!(
+                    "{} must be a date, an RFC 3339 time or a unix timestamp before 2154",
+                    name
+                );
+                return;
+            }
+        };
+        // A range with a start is fetched completely unless a count is given
+        let max_count = match fetch.value_of("max-count") {
+            Some(count) => match count.parse() {
+                Ok(c) => Some(c),
+                Err(_) => {
+                    error!("max-count must be an integer");
+                    return;
+                }
+            },
+            None if since.is_some() => None,
+            None => Some(500),
+        };
+        let jobs: usize = match fetch.value_of("jobs").unwrap_or("4").parse() {
+            Ok(jobs) if jobs > 0 => jobs,
+            _ => {
+                error!("jobs must be a positive integer");
                 return;
             }
         };
@@ -239,7 +479,7 @@ fn main() {
         let tracked_channels = match config.tracked_channels() {
             Ok(channels) => channels,
             Err(e) => {
-                eprintln!("Error loading channels:\n{:?}", e);
+                error!("Error loading channels:\n{:?}", e);
                 std::process::exit(2);
             }
         };
@@ -249,100 +489,386 @@ fn main() {
                 channel_id,
             });
         }
-        if let Ok(logged_channels) = stats.get_channels() {
+        if let Ok(logged_channels) = stats.get_channels(&store::Filter::default()) {
             channels_to_scan.extend(logged_channels)
         }
 
-        println!("Scanning:");
-
-        scan::MessageScanner { data, store: stats }.scan_messages(&channels_to_scan, max_count);
-
+        let range = scan::ScanRange {
+            max_count,
+            since,
+            until,
+        };
+        fetch_history(data, &stats, &rest, &channels_to_scan, range, jobs);
         return;
     }
 
-    // start web server
-    let http_stats = stats.clone();
-    thread::spawn(|| {
-        println!("Starting webserver");
-
-        let router = router! {
-            api_total_msg_count_per_day: get "/api/total_msg_count_per_day" => api::total_msg_count_per_day,
-            api_user_msg_count_per_day: get "/api/user_msg_count_per_day" => api::msg_count_per_day,
-            api_total_msg_count: get "/api/total_msg_count" => api::total_msg_count,
-            api_edit_count: get "/api/edit_count" => api::edit_count,
-            api_channels: get "/api/channels" => api::get_channels,
-            api_msg_count: get "/api/msg_count" => api::msg_count,
-            dashboard_js: get "/index.js" => api::dashboard_js,
-            api_guilds: get "/api/guilds" => api::get_guilds,
-            dashboard_g: get "/*" => api::dashboard,
-            dashboard: get "/" => api::dashboard,
-        };
+    let events = Arc::new(EventBus::default());
 
-        let mut chain = Chain::new(router);
-        chain.link(Read::<api::Stats>::both(http_stats));
-        let server = Iron::new(chain).http("localhost:8080");
-        if let Err(e) = server {
-            eprintln!("Unable to create http servere on port 8080: {:?}", e)
+    let listening = if config.web.enabled {
+        info!("Starting webserver");
+        match web::serve(&config.web, &config.analysis, stats.clone(), events.clone()) {
+            Ok(listening) => Some(listening),
+            Err(e) => {
+                error!(
+                    "Unable to create http server on {}:{}: {:?}",
+                    config.web.bind_address, config.web.port, e
+                );
+                None
+            }
         }
-    });
+    } else {
+        None
+    };
 
     let tracked_channels = match config.tracked_channels() {
         Ok(channels) => channels,
         Err(e) => {
-            eprintln!("Config contains invalid tracked channels:\n{:?}", e);
+            error!("Config contains invalid tracked channels:\n{:?}", e);
             std::process::exit(2)
         }
     };
 
+    METRICS
+        .tracked_channels
+        .store(tracked_channels.len() as u64, Ordering::Relaxed);
+    METRICS.collecting.store(true, Ordering::Relaxed);
+
     // start discord client
-    let handler = event_handler::Handler::new(stats.clone(), tracked_channels);
+    let handler = event_handler::Handler::new(stats.clone(), events, rest, tracked_channels);
     let mut client = match Client::new(&token, handler) {
         Ok(client) => client,
         Err(e) => {
-            eprintln!("Error starting discord client: {:#?}", e);
+            error!("Error starting discord client: {:#?}", e);
             std::process::exit(3)
         }
     };
 
+    // Stopping the shards makes `start` return
+    let shard_manager = Arc::clone(&client.shard_manager);
+    shutdown::install(move || shard_manager.lock().shutdown_all());
+
     if let Err(why) = client.start() {
-        eprintln!("Unable to connect to discord: {:?}", why);
+        error!("Unable to connect to discord: {:?}", why);
     }
+    shut_down(listening, &stats);
+}
+
+/// Lets the user pick channels to track and optionally fetches their history
+fn track_interactive(
+    config: &mut Config,
+    token: &str,
+    rest: &Arc<RestClient>,
+    db_path: &Path,
+    fetch: bool,
+) {
+    let term = Term::stderr();
+    if !term.is_term() {
+        error!("The interactive picker needs a terminal");
+        std::process::exit(2)
+    }
+
+    let data = get_oneshot_data(token);
+    let candidates = picker::candidates(&data, rest);
+    let picked = match picker::pick(&term, &candidates) {
+        Ok(Some(picked)) => picked,
+        Ok(None) => return,
+        Err(e) => {
+            error!("Unable to read from the terminal: {:?}", e);
+            std::process::exit(2)
+        }
+    };
+
+    let mut channels = HashSet::new();
+    for candidate in picked.into_iter().map(|index| &candidates[index]) {
+        let id_str = match candidate.guild_id {
+            Some(guild_id) => format!("{}|{}", guild_id.0, candidate.channel_id.0),
+            None => candidate.channel_id.0.to_string(),
+        };
+        if !config.tracked_channels.contains(&id_str) {
+            config.tracked_channels.push(id_str);
+        }
+        println!("Tracking {}", candidate.label);
+        channels.insert(store::Channel {
+            guild_id: candidate.guild_id,
+            channel_id: candidate.channel_id,
+        });
+    }
+    if let Err(e) = config.save() {
+        error!("An error occured saving the configuration file:\n{:?}", e);
+    }
+
+    if fetch && !channels.is_empty() {
+        let stats = match StatsStore::new(db_path) {
+            Ok(conn) => Arc::new(conn),
+            Err(e) => {
+                error!("Unable to open database:\n{:?}", e);
+                std::process::exit(2)
+            }
+        };
+        let range = scan::ScanRange {
+            max_count: Some(500),
+            since: None,
+            until: None,
+        };
+        fetch_history(data, &stats, rest, &channels, range, 4);
+    }
+}
+
+/// Scans the channels, stopping early and saving progress on SIGINT or SIGTERM
+fn fetch_history(
+    data: OneshotData,
+    stats: &Arc<StatsStore>,
+    rest: &Arc<RestClient>,
+    channels: &HashSet<store::Channel>,
+    range: scan::ScanRange,
+    jobs: usize,
+) {
+    println!("Scanning:");
+
+    // Scans check for the signal between pages and save their progress
+    shutdown::install(|| {});
+    scan::MessageScanner {
+        data,
+        store: Arc::clone(stats),
+        rest: Arc::clone(rest),
+        jobs,
+    }
+    .scan_messages(channels, range);
+
+    shut_down(None, stats);
+}
+
+/// Prints the statistics of the messages selected by the arguments
+fn print_summary(matches: &clap::ArgMatches, db_path: &Path) -> Result<(), String> {
+    let filter = parse_filter(matches)?;
+
+    if !db_path.exists() {
+        return Err(format!("No database found at {}", db_path.display()));
+    }
+    let stats =
+        StatsStore::new(db_path).map_err(|e| format!("Unable to open database:\n{:?}", e))?;
+    let summary = summary::build(&stats, &filter)
+        .map_err(|e| format!("Unable to read statistics:\n{:?}", e))?;
+
+    if matches.is_present("json") {
+        let json = serde_json::to_string_pretty(&summary)
+            .map_err(|e| format!("Unable to serialize statistics: {}", e))?;
+        println!("{}", json);
+    } else {
+        print!("{}", summary::render(&summary));
+    }
+    Ok(())
+}
+
+/// Writes the year in review as `year-in-review-<year>.html` and `.md`
+fn write_report(
+    matches: &clap::ArgMatches,
+    db_path: &Path,
+    stopwords: &Option<Vec<String>>,
+) -> Result<(), String> {
+    let year = match matches.value_of("year") {
+        Some(year) => year
+            .parse()
+            .map_err(|_| format!("year must be a number: {}", year))?,
+        None => chrono::Utc::now().year(),
+    };
+    let output = Path::new(matches.value_of("output").unwrap_or("."));
+
+    if !db_path.exists() {
+        return Err(format!("No database found at {}", db_path.display()));
+    }
+    let stats =
+        StatsStore::new(db_path).map_err(|e| format!("Unable to open database:\n{:?}", e))?;
+    let report = report::build(&stats, year, stopwords)
+        .map_err(|e| format!("Unable to build the report: {}", e))?;
+
+    DirBuilder::new()
+        .recursive(true)
+        .create(output)
+        .map_err(|e| format!("Unable to create {}: {}", output.display(), e))?;
+    for (extension, content) in &[
+        ("html", report::html(&report)),
+        ("md", report::markdown(&report)),
+    ] {
+        let path = output.join(format!("year-in-review-{}.{}", year, extension));
+        std::fs::write(&path, content)
+            .map_err(|e| format!("Unable to write {}: {}", path.display(), e))?;
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+/// Writes the interaction graph of the messages selected by the arguments
+fn export_graph(matches: &clap::ArgMatches, db_path: &Path) -> Result<(), String> {
+    let format: GraphFormat = matches.value_of("format").unwrap_or("dot").parse()?;
+    let filter = parse_filter(matches)?;
+
+    if !db_path.exists() {
+        return Err(format!("No database found at {}", db_path.display()));
+    }
+    let stats =
+        StatsStore::new(db_path).map_err(|e| format!("Unable to open database:\n{:?}", e))?;
+    let graph = stats
+        .get_interaction_graph(&filter)
+        .map_err(|e| format!("Unable to build the graph:\n{:?}", e))?;
+    let rendered =
+        graph::render(&graph, format).map_err(|e| format!("Unable to write the graph: {}", e))?;
+
+    match matches.value_of("output") {
+        Some(path) => std::fs::write(path, rendered)
+            .map_err(|e| format!("Unable to write {}: {}", path, e))?,
+        None => print!("{}", rendered),
+    }
+    info!(
+        "Exported {} users and {} interactions",
+        graph.users.len(),
+        graph.interactions.len()
+    );
+    Ok(())
+}
+
+fn parse_id_list(value: &str, name: &str) -> Result<Vec<u64>, String> {
+    value
+        .split(',')
+        .filter(|id| !id.is_empty())
+        .map(|id| {
+            id.trim()
+                .parse()
+                .map_err(|_| format!("invalid {} id: {}", name, id))
+        })
+        .collect()
+}
+
+/// Stops accepting requests and flushes the database before the process exits
+fn shut_down(listening: Option<Listening>, stats: &StatsStore) {
+    if let Some(mut listening) = listening {
+        // hyper can not stop running workers, they end with the process
+        let _ = listening.close();
+    }
+    if let Err(e) = stats.close() {
+        error!("Unable to flush the database: {:?}", e);
+    }
+}
+
+/// The first message id at the time given to an option, the option's name if it is invalid
+fn snowflake_arg<'a>(
+    matches: &clap::ArgMatches,
+    name: &'a str,
+    bound: Bound,
+) -> Result<Option<MessageId>, &'a str> {
+    match matches.value_of(name) {
+        Some(value) => time::parse_time(value, bound)
+            .and_then(time::snowflake_at)
+            .map(|id| Some(MessageId(id)))
+            .ok_or(name),
+        None => Ok(None),
+    }
+}
+
+/// Arguments selecting messages, like the API's standard query parameters
+fn filter_args<'a, 'b>() -> Vec<clap::Arg<'a, 'b>> {
+    use clap::Arg;
+    vec![
+        Arg::with_name("guild")
+            .help("Only messages in these comma separated guild ids")
+            .long("guild")
+            .takes_value(true),
+        Arg::with_name("channel")
+            .help("Only messages in these comma separated channel ids and their threads")
+            .long("channel")
+            .takes_value(true),
+        Arg::with_name("since")
+            .help("Only messages sent at or after this date or time")
+            .long("since")
+            .takes_value(true),
+        Arg::with_name("until")
+            .help("Only messages sent before this time or up to this date")
+            .long("until")
+            .takes_value(true),
+    ]
+}
+
+/// Builds the filter from the arguments of `filter_args`
+fn parse_filter(matches: &clap::ArgMatches) -> Result<Filter, String> {
+    let mut filter = Filter::default();
+    if let Some(guilds) = matches.value_of("guild") {
+        filter.guilds = Some(
+            parse_id_list(guilds, "guild")?
+                .into_iter()
+                .map(GuildId)
+                .collect(),
+        );
+    }
+    if let Some(channels) = matches.value_of("channel") {
+        filter.channels = Some(
+            parse_id_list(channels, "channel")?
+                .into_iter()
+                .map(ChannelId)
+                .collect(),
+        );
+    }
+    for &(name, bound) in &[("since", Bound::Start), ("until", Bound::End)] {
+        if let Some(value) = matches.value_of(name) {
+            let time = time::parse_time(value, bound).ok_or_else(|| {
+                format!(
+                    "{} must be a date, an RFC 3339 time or a unix timestamp",
+                    name
+                )
+            })?;
+            match bound {
+                Bound::Start => filter.since = Some(time),
+                Bound::End => filter.until = Some(time),
+            }
+        }
+    }
+    Ok(filter)
+}
+
+/// Options overriding the `[web]` section of the configuration
+fn web_args<'a, 'b>() -> Vec<clap::Arg<'a, 'b>> {
+    use clap::Arg;
+    vec![
+        Arg::with_name("bind")
+            .help("Address the web server binds to")
+            .long("bind")
+            .takes_value(true),
+        Arg::with_name("port")
+            .help("Port the web server listens on")
+            .long("port")
+            .takes_value(true),
+        Arg::with_name("base-path")
+            .help("Path prefix to serve the dashboard under")
+            .long("base-path")
+            .takes_value(true),
+    ]
+}
+
+/// Looks up a global argument, which clap only records on the subcommand it was passed after
+fn global_value_of<'a>(matches: &'a clap::ArgMatches, name: &str) -> Option<&'a OsStr> {
+    matches.value_of_os(name).or_else(|| {
+        matches
+            .subcommand()
+            .1
+            .and_then(|sub_matches| sub_matches.value_of_os(name))
+    })
 }
 
 fn resolve_guild_channel_names(
     data: &OneshotData,
+    rest: &RestClient,
     guild_name: &str,
     channel_name: &str,
 ) -> Option<(GuildId, ChannelId)> {
-    for guild in &data.ready.guilds {
-        use serenity::model::guild::GuildStatus::*;
-        let (guild_id, name) = match guild {
-            OnlinePartialGuild(g) => (g.id, g.name.clone()),
-            OnlineGuild(g) => (g.id, g.name.clone()),
-            Offline(g) => (
-                g.id,
-                g.id.to_partial_guild(&data.context.http)
-                    .expect("Unable to fetch guild data")
-                    .name
-                    .clone(),
-            ),
-            _ => panic!("Unknown guild state"),
-        };
-
+    for (guild_id, name) in data.guilds() {
         if guild_name.to_lowercase() == name.to_lowercase() {
-            let channels = guild_id
-                .channels(&data.context.http)
+            // Text, news and forum channels, threads are recorded with the channel they are in
+            let channels = rest
+                .text_channels(guild_id)
                 .expect("Unable to fetch guild channels");
 
-            for (&channel_id, channel) in channels.iter() {
-                use serenity::model::channel::ChannelType::*;
-                match channel.kind {
-                    Text | Private | Group | News => {}
-                    _ => continue,
-                }
-
+            for channel in channels {
                 if channel_name.to_lowercase() == channel.name.to_lowercase() {
-                    return Some((guild_id, channel_id));
+                    return Some((guild_id, channel.id));
                 }
             }
         }
@@ -350,11 +876,37 @@ fn resolve_guild_channel_names(
     None
 }
 
-fn resolve_private_channel(data: &OneshotData, user_name: &str) -> Option<ChannelId> {
+/// Finds a direct message by user name, or a group by its name or members
+///
+/// Group members are given as comma separated user names in any order.
+fn resolve_private_channel(data: &OneshotData, name: &str) -> Option<ChannelId> {
+    let name = name.to_lowercase();
+    let own_name = data.ready.user.name.to_lowercase();
+    let members = name
+        .split(',')
+        .map(str::trim)
+        .filter(|member| !member.is_empty() && *member != own_name)
+        .collect::<HashSet<_>>();
+
     for (&id, channel) in data.ready.private_channels.iter() {
-        if user_name.to_lowercase() == format!("{}", channel).to_lowercase() {
+        if name == format!("{}", channel).to_lowercase() {
             return Some(id);
         }
+        if let Channel::Group(group) = channel {
+            let recipients = group
+                .read()
+                .recipients
+                .values()
+                .map(|user| user.read().name.to_lowercase())
+                .collect::<Vec<_>>();
+            if recipients.len() == members.len()
+                && recipients
+                    .iter()
+                    .all(|recipient| members.contains(recipient.as_str()))
+            {
+                return Some(id);
+            }
+        }
     }
     None
 }
@@ -364,13 +916,13 @@ fn get_oneshot_data(token: &str) -> OneshotData {
     let mut client = match Client::new(&token, handler) {
         Ok(client) => client,
         Err(e) => {
-            eprintln!("Error starting discord client: {:#?}", e);
+            error!("Error starting discord client: {:#?}", e);
             std::process::exit(3);
         }
     };
     thread::spawn(move || {
         if let Err(e) = client.start() {
-            eprintln!("Unable to connect to discord: {:#?}", e);
+            error!("Unable to connect to discord: {:#?}", e);
         }
     });
     rx.recv().expect("event handler should not panic")
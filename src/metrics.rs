@@ -0,0 +1,186 @@
+use std::fmt::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+
+use crate::store::{Filter, StatsStore};
+
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    const fn new() -> Counter {
+        Counter(AtomicU64::new(0))
+    }
+
+    pub fn inc(&self) {
+        self.add(1)
+    }
+
+    pub fn add(&self, amount: u64) {
+        self.0.fetch_add(amount, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Process wide counters, exported in the Prometheus text format at `/metrics`
+pub struct Metrics {
+    /// Whether this process runs the Discord client
+    pub collecting: AtomicBool,
+    pub gateway_connected: AtomicBool,
+    /// Unix timestamp of the last gateway event, 0 before the first
+    pub last_event: AtomicI64,
+    /// Unix timestamp the process started at
+    pub started_at: AtomicI64,
+    pub tracked_channels: AtomicU64,
+    pub gateway_reconnects: Counter,
+    pub messages_recorded: Counter,
+    pub edits_recorded: Counter,
+    pub deletions_recorded: Counter,
+    pub insert_errors: Counter,
+    pub scanned_messages: Counter,
+    pub scan_fetch_errors: Counter,
+}
+
+pub static METRICS: Metrics = Metrics {
+    collecting: AtomicBool::new(false),
+    gateway_connected: AtomicBool::new(false),
+    last_event: AtomicI64::new(0),
+    started_at: AtomicI64::new(0),
+    tracked_channels: AtomicU64::new(0),
+    gateway_reconnects: Counter::new(),
+    messages_recorded: Counter::new(),
+    edits_recorded: Counter::new(),
+    deletions_recorded: Counter::new(),
+    insert_errors: Counter::new(),
+    scanned_messages: Counter::new(),
+    scan_fetch_errors: Counter::new(),
+};
+
+impl Metrics {
+    pub fn mark_started(&self) {
+        self.started_at
+            .store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    pub fn mark_event(&self) {
+        self.last_event
+            .store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    pub fn uptime(&self) -> i64 {
+        chrono::Utc::now().timestamp() - self.started_at.load(Ordering::Relaxed)
+    }
+
+    pub fn last_event(&self) -> Option<i64> {
+        match self.last_event.load(Ordering::Relaxed) {
+            0 => None,
+            time => Some(time),
+        }
+    }
+}
+
+/// Renders all metrics, querying the store for its gauges
+pub fn render(store: &StatsStore) -> String {
+    let mut out = String::new();
+    let m = &METRICS;
+
+    let gauges: &[(&str, &str, Option<i64>)] = &[
+        (
+            "discord_stats_gateway_connected",
+            "Whether the Discord gateway connection is established",
+            Some(m.gateway_connected.load(Ordering::Relaxed) as i64),
+        ),
+        (
+            "discord_stats_last_event_timestamp_seconds",
+            "Time of the last gateway event",
+            m.last_event(),
+        ),
+        (
+            "discord_stats_uptime_seconds",
+            "Time since the process started",
+            Some(m.uptime()),
+        ),
+        (
+            "discord_stats_tracked_channels",
+            "Channels tracked in addition to the user's own messages",
+            Some(m.tracked_channels.load(Ordering::Relaxed) as i64),
+        ),
+        (
+            "discord_stats_database_size_bytes",
+            "Size of the sqlite database files",
+            store.database_size().ok().map(|size| size as i64),
+        ),
+        (
+            "discord_stats_stored_messages",
+            "Messages in the database",
+            store.get_msg_count(&Filter::default()).ok(),
+        ),
+        (
+            "discord_stats_stored_edits",
+            "Edits in the database",
+            store.get_edit_count(&Filter::default()).ok(),
+        ),
+        (
+            "discord_stats_stored_deletions",
+            "Deletions in the database",
+            store.get_deletion_count(&Filter::default()).ok(),
+        ),
+    ];
+    for &(name, help, value) in gauges {
+        // Leave out gauges that could not be read rather than report a wrong value
+        if let Some(value) = value {
+            write_metric(&mut out, name, help, "gauge", value);
+        }
+    }
+
+    let counters: &[(&str, &str, &Counter)] = &[
+        (
+            "discord_stats_gateway_reconnects_total",
+            "Times the gateway connection was resumed or re-established",
+            &m.gateway_reconnects,
+        ),
+        (
+            "discord_stats_messages_recorded_total",
+            "Messages recorded from the gateway",
+            &m.messages_recorded,
+        ),
+        (
+            "discord_stats_edits_recorded_total",
+            "Edits recorded from the gateway",
+            &m.edits_recorded,
+        ),
+        (
+            "discord_stats_deletions_recorded_total",
+            "Deletions recorded from the gateway",
+            &m.deletions_recorded,
+        ),
+        (
+            "discord_stats_insert_errors_total",
+            "Failed database writes of gateway events",
+            &m.insert_errors,
+        ),
+        (
+            "discord_stats_scanned_messages_total",
+            "Messages fetched by history scans",
+            &m.scanned_messages,
+        ),
+        (
+            "discord_stats_scan_fetch_errors_total",
+            "Failed requests for message history",
+            &m.scan_fetch_errors,
+        ),
+    ];
+    for &(name, help, counter) in counters {
+        write_metric(&mut out, name, help, "counter", counter.get());
+    }
+
+    out
+}
+
+fn write_metric(out: &mut String, name: &str, help: &str, kind: &str, value: impl fmt::Display) {
+    // Writing to a string can not fail
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
@@ -0,0 +1,1146 @@
+use serde_json::{json, Map, Value};
+
+use crate::analysis::sessions::DEFAULT_SESSION_GAP;
+use crate::graph::GraphFormat;
+
+pub const API_VERSION: &str = "1.0.0";
+
+/// Builds the OpenAPI 3 document served at `/api/v1/openapi.json`
+///
+/// This describes the response types in `api.rs` and must be updated with them, the tests
+/// below check serialized responses against it.
+pub fn document() -> Value {
+    let mut paths = Map::new();
+    for (path, summary, schema, filtered) in endpoints() {
+        let mut operation = operation(summary, schema, filtered);
+        if let Some(parameters) = operation["get"]["parameters"].as_array_mut() {
+            parameters.extend(endpoint_parameters(path));
+        }
+        if let Some(content) = operation["get"]["responses"]["200"]["content"].as_object_mut() {
+            content.extend(alternative_content(path));
+        }
+        paths.insert(path.to_owned(), operation);
+    }
+    paths.insert("/events".to_owned(), event_stream_operation());
+
+    json!({
+        "openapi": "3.0.2",
+        "info": {
+            "title": "Discord Statistics",
+            "version": API_VERSION,
+        },
+        // Relative to the document, so it works behind a base path
+        "servers": [{ "url": "." }],
+        "paths": paths,
+        "components": {
+            "schemas": schemas(),
+            "parameters": filter_parameters(),
+            "securitySchemes": {
+                "bearer": { "type": "http", "scheme": "bearer" },
+                "basic": { "type": "http", "scheme": "basic" },
+            },
+        },
+        "security": [{}, { "bearer": [] }, { "basic": [] }],
+    })
+}
+
+/// Path, summary, response schema and whether the standard filters apply
+fn endpoints() -> Vec<(&'static str, &'static str, Value, bool)> {
+    vec![
+        (
+            "/total_msg_count",
+            "Number of logged messages",
+            schema_ref("Count"),
+            true,
+        ),
+        (
+            "/msg_count",
+            "Number of messages sent by the current user",
+            schema_ref("Count"),
+            true,
+        ),
+        (
+            "/edit_count",
+            "Number of logged edits",
+            schema_ref("Count"),
+            true,
+        ),
+        (
+            "/total_msg_count_per_day",
+            "Logged messages per day",
+            array_of("DailyCount"),
+            true,
+        ),
+        (
+            "/user_msg_count_per_day",
+            "Messages sent by the current user per day over the last week",
+            array_of("DailyCount"),
+            true,
+        ),
+        (
+            "/channels",
+            "Channels with logged messages",
+            array_of("Channel"),
+            true,
+        ),
+        (
+            "/guilds",
+            "Guilds with logged messages",
+            array_of("Guild"),
+            true,
+        ),
+        (
+            "/sessions",
+            "Conversations found by splitting channels at pauses of `gap` minutes",
+            schema_ref("SessionStats"),
+            true,
+        ),
+        (
+            "/response_times",
+            "Median time users take to answer others within a session",
+            array_of("ResponseTime"),
+            true,
+        ),
+        (
+            "/words",
+            "Most used words, vocabulary size per month and words characteristic for channels",
+            schema_ref("WordStats"),
+            true,
+        ),
+        (
+            "/emoji",
+            "Unicode and custom emoji used in messages, per user, guild and month",
+            schema_ref("EmojiStats"),
+            true,
+        ),
+        (
+            "/code",
+            "Fenced code blocks and lines of code per language, user, channel and month",
+            schema_ref("CodeStats"),
+            true,
+        ),
+        (
+            "/link_domains",
+            "Domains by the number of links shared to them, short links grouped with their site",
+            array_of("DomainCount"),
+            true,
+        ),
+        (
+            "/top_links",
+            "Most shared links",
+            array_of("SharedLink"),
+            true,
+        ),
+        (
+            "/links_per_day",
+            "Links shared per day, days without links are left out",
+            array_of("DailyLinkCount"),
+            true,
+        ),
+        (
+            "/interaction_graph",
+            "Users and how often they mentioned or replied to each other",
+            schema_ref("InteractionGraph"),
+            true,
+        ),
+        (
+            "/status",
+            "Health of the collector and database, responds with 503 if not ok and with 403 \
+             to API keys limited to some guilds",
+            schema_ref("Status"),
+            false,
+        ),
+        (
+            "/openapi.json",
+            "This document",
+            json!({ "type": "object" }),
+            false,
+        ),
+    ]
+}
+
+fn operation(summary: &str, schema: Value, filtered: bool) -> Value {
+    let parameters: Vec<Value> = if filtered {
+        ["guild", "channel", "since", "until"]
+            .iter()
+            .map(|name| json!({ "$ref": format!("#/components/parameters/{}", name) }))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    json!({
+        "get": {
+            "summary": summary,
+            "parameters": parameters,
+            "responses": {
+                "200": {
+                    "description": "Success",
+                    "content": { "application/json": { "schema": schema } },
+                },
+                "default": {
+                    "description": "Error",
+                    "content": { "application/json": { "schema": schema_ref("Error") } },
+                },
+            },
+        }
+    })
+}
+
+/// Query parameters of single endpoints, in addition to the filters
+fn endpoint_parameters(path: &str) -> Vec<Value> {
+    match path {
+        "/sessions" | "/response_times" => vec![json!({
+            "name": "gap",
+            "in": "query",
+            "required": false,
+            "description": "Minutes without messages that end a session",
+            "schema": {
+                "type": "integer",
+                "minimum": 1,
+                "default": DEFAULT_SESSION_GAP / 60,
+            },
+        })],
+        "/interaction_graph" => vec![json!({
+            "name": "format",
+            "in": "query",
+            "required": false,
+            "description": "Format of the graph, JSON by default",
+            "schema": { "type": "string", "enum": ["json", "dot", "graphml"] },
+        })],
+        _ => Vec::new(),
+    }
+}
+
+/// Response content types other than JSON, chosen by query parameters
+fn alternative_content(path: &str) -> Vec<(String, Value)> {
+    match path {
+        "/interaction_graph" => [GraphFormat::Dot, GraphFormat::Graphml]
+            .iter()
+            .map(|format| {
+                let schema = json!({ "schema": { "type": "string" } });
+                (format.content_type().to_owned(), schema)
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn event_stream_operation() -> Value {
+    json!({
+        "get": {
+            "summary": "Stream of changes as server-sent events",
+            "description": "Events are named `message`, `edit` and `deletion` with a \
+                `LiveEvent` as data, each followed by a `counters` event with `Counters`.",
+            "parameters": [
+                { "$ref": "#/components/parameters/guild" },
+                { "$ref": "#/components/parameters/channel" },
+            ],
+            "responses": {
+                "200": {
+                    "description": "Success",
+                    "content": { "text/event-stream": { "schema": { "type": "string" } } },
+                },
+            },
+        }
+    })
+}
+
+fn schemas() -> Value {
+    json!({
+        "Count": {
+            "type": "object",
+            "required": ["count"],
+            "properties": { "count": { "type": "integer" } },
+        },
+        "DailyCount": {
+            "type": "object",
+            "required": ["date", "public", "private"],
+            "properties": {
+                "date": { "type": "string", "format": "date" },
+                "public": { "type": "integer", "description": "Messages in guilds" },
+                "private": { "type": "integer", "description": "Direct messages" },
+            },
+        },
+        "Channel": {
+            "type": "object",
+            "required": ["channel_id", "guild_id"],
+            "properties": {
+                "channel_id": { "type": "string" },
+                "guild_id": { "type": "string", "nullable": true },
+            },
+        },
+        "Guild": {
+            "type": "object",
+            "required": ["guild_id"],
+            "properties": { "guild_id": { "type": "string" } },
+        },
+        "UserCount": {
+            "type": "object",
+            "required": ["user_id", "name", "count"],
+            "properties": {
+                "user_id": { "type": "string" },
+                "name": { "type": "string", "nullable": true },
+                "count": { "type": "integer" },
+            },
+        },
+        "SessionStats": {
+            "type": "object",
+            "required": [
+                "gap_seconds",
+                "sessions",
+                "median_length_seconds",
+                "mean_length_seconds",
+                "longest_seconds",
+                "mean_messages",
+                "starters",
+            ],
+            "properties": {
+                "gap_seconds": { "type": "integer" },
+                "sessions": { "type": "integer" },
+                "median_length_seconds": { "type": "integer" },
+                "mean_length_seconds": { "type": "number" },
+                "longest_seconds": { "type": "integer" },
+                "mean_messages": { "type": "number" },
+                "starters": {
+                    "type": "array",
+                    "description": "Users by the number of sessions they started",
+                    "items": schema_ref("UserCount"),
+                },
+            },
+        },
+        "ResponseTime": {
+            "type": "object",
+            "required": ["user_id", "name", "responses", "median_seconds"],
+            "properties": {
+                "user_id": { "type": "string" },
+                "name": { "type": "string", "nullable": true },
+                "responses": { "type": "integer" },
+                "median_seconds": { "type": "integer" },
+            },
+        },
+        "TextCount": {
+            "type": "object",
+            "required": ["text", "count"],
+            "properties": {
+                "text": { "type": "string" },
+                "count": { "type": "integer" },
+            },
+        },
+        "WordStats": {
+            "type": "object",
+            "required": [
+                "messages",
+                "average_words",
+                "average_characters",
+                "words",
+                "bigrams",
+                "vocabulary",
+                "channels",
+            ],
+            "properties": {
+                "messages": { "type": "integer" },
+                "average_words": { "type": "number" },
+                "average_characters": { "type": "number" },
+                "words": { "type": "array", "items": schema_ref("TextCount") },
+                "bigrams": {
+                    "type": "array",
+                    "description": "Pairs of adjacent words, separated by a space",
+                    "items": schema_ref("TextCount"),
+                },
+                "vocabulary": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["month", "distinct", "new", "cumulative"],
+                        "properties": {
+                            "month": { "type": "string", "description": "As YYYY-MM" },
+                            "distinct": { "type": "integer" },
+                            "new": { "type": "integer" },
+                            "cumulative": { "type": "integer" },
+                        },
+                    },
+                },
+                "channels": {
+                    "type": "array",
+                    "description": "Words used more in one channel than in others, by TF-IDF",
+                    "items": {
+                        "type": "object",
+                        "required": ["channel_id", "words"],
+                        "properties": {
+                            "channel_id": { "type": "string" },
+                            "words": {
+                                "type": "array",
+                                "items": {
+                                    "type": "object",
+                                    "required": ["text", "score"],
+                                    "properties": {
+                                        "text": { "type": "string" },
+                                        "score": { "type": "number" },
+                                    },
+                                },
+                            },
+                        },
+                    },
+                },
+            },
+        },
+        "EmojiCount": {
+            "type": "object",
+            "required": ["emoji", "id", "image_url", "count"],
+            "properties": {
+                "emoji": {
+                    "type": "string",
+                    "description": "The emoji itself, or the name of a custom emoji",
+                },
+                "id": { "type": "string", "nullable": true },
+                "image_url": { "type": "string", "nullable": true },
+                "count": { "type": "integer" },
+            },
+        },
+        "EmojiStats": {
+            "type": "object",
+            "required": ["total", "emoji", "users", "guilds", "months"],
+            "properties": {
+                "total": { "type": "integer" },
+                "emoji": { "type": "array", "items": schema_ref("EmojiCount") },
+                "users": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["user_id", "name", "count", "top"],
+                        "properties": {
+                            "user_id": { "type": "string" },
+                            "name": { "type": "string", "nullable": true },
+                            "count": { "type": "integer" },
+                            "top": { "type": "array", "items": schema_ref("EmojiCount") },
+                        },
+                    },
+                },
+                "guilds": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["guild_id", "count", "top"],
+                        "properties": {
+                            "guild_id": { "type": "string" },
+                            "count": { "type": "integer" },
+                            "top": { "type": "array", "items": schema_ref("EmojiCount") },
+                        },
+                    },
+                },
+                "months": { "type": "array", "items": schema_ref("MonthCount") },
+            },
+        },
+        "MonthCount": {
+            "type": "object",
+            "required": ["month", "count"],
+            "properties": {
+                "month": { "type": "string", "description": "As YYYY-MM" },
+                "count": { "type": "integer" },
+            },
+        },
+        "CodeStats": {
+            "type": "object",
+            "required": ["blocks", "lines", "languages", "users", "channels", "months"],
+            "properties": {
+                "blocks": { "type": "integer" },
+                "lines": { "type": "integer" },
+                "languages": {
+                    "type": "array",
+                    "items": code_count(json!({
+                        "language": {
+                            "type": "string",
+                            "nullable": true,
+                            "description": "Null for blocks without a declared language",
+                        },
+                    })),
+                },
+                "users": {
+                    "type": "array",
+                    "items": code_count(json!({
+                        "user_id": { "type": "string" },
+                        "name": { "type": "string", "nullable": true },
+                    })),
+                },
+                "channels": {
+                    "type": "array",
+                    "items": code_count(json!({ "channel_id": { "type": "string" } })),
+                },
+                "months": {
+                    "type": "array",
+                    "items": code_count(json!({
+                        "month": { "type": "string", "description": "As YYYY-MM" },
+                    })),
+                },
+            },
+        },
+        "DomainCount": {
+            "type": "object",
+            "required": ["domain", "count"],
+            "properties": {
+                "domain": { "type": "string" },
+                "count": { "type": "integer" },
+            },
+        },
+        "SharedLink": {
+            "type": "object",
+            "required": ["url", "domain", "count", "first_shared"],
+            "properties": {
+                "url": { "type": "string" },
+                "domain": { "type": "string" },
+                "count": { "type": "integer" },
+                "first_shared": { "type": "integer", "description": "Unix timestamp" },
+            },
+        },
+        "DailyLinkCount": {
+            "type": "object",
+            "required": ["date", "count"],
+            "properties": {
+                "date": { "type": "string", "format": "date" },
+                "count": { "type": "integer" },
+            },
+        },
+        "InteractionGraph": {
+            "type": "object",
+            "description": "Replies are messages sent with Discord's reply to another user's \
+                message. With `format=dot` or `format=graphml` the graph is returned in that \
+                format instead.",
+            "required": ["nodes", "edges"],
+            "properties": {
+                "nodes": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["id", "name", "messages"],
+                        "properties": {
+                            "id": { "type": "string" },
+                            "name": { "type": "string", "nullable": true },
+                            "messages": { "type": "integer" },
+                        },
+                    },
+                },
+                "edges": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["source", "target", "weight", "mentions", "replies"],
+                        "properties": {
+                            "source": { "type": "string" },
+                            "target": { "type": "string" },
+                            "weight": { "type": "integer" },
+                            "mentions": { "type": "integer" },
+                            "replies": { "type": "integer" },
+                        },
+                    },
+                },
+            },
+        },
+        "Counters": {
+            "type": "object",
+            "required": ["total", "user", "edits"],
+            "properties": {
+                "total": { "type": "integer" },
+                "user": { "type": "integer" },
+                "edits": { "type": "integer" },
+            },
+        },
+        "LiveEvent": {
+            "type": "object",
+            "required": ["type", "message_id", "channel_id", "guild_id"],
+            "properties": {
+                "type": { "type": "string", "enum": ["message", "edit", "deletion"] },
+                "message_id": { "type": "string" },
+                "channel_id": { "type": "string" },
+                "guild_id": { "type": "string", "nullable": true },
+                "author_id": { "type": "string", "description": "Only on messages" },
+                "time": { "type": "integer", "description": "Only on messages" },
+            },
+        },
+        "Status": {
+            "type": "object",
+            "required": [
+                "ok",
+                "collecting",
+                "gateway_connected",
+                "user",
+                "last_event",
+                "tracked_channels",
+                "schema_version",
+                "database_size",
+                "database_writable",
+                "uptime_seconds",
+            ],
+            "properties": {
+                "ok": { "type": "boolean" },
+                "collecting": { "type": "boolean" },
+                "gateway_connected": { "type": "boolean" },
+                "user": {
+                    "type": "object",
+                    "nullable": true,
+                    "required": ["id", "name"],
+                    "properties": {
+                        "id": { "type": "string" },
+                        "name": { "type": "string", "nullable": true },
+                    },
+                },
+                "last_event": { "type": "integer", "nullable": true },
+                "tracked_channels": { "type": "integer" },
+                "schema_version": { "type": "integer" },
+                "database_size": { "type": "integer", "nullable": true },
+                "database_writable": { "type": "boolean" },
+                "uptime_seconds": { "type": "integer" },
+            },
+        },
+        "Error": {
+            "type": "object",
+            "required": ["error", "kind"],
+            "properties": {
+                "error": { "type": "string" },
+                "kind": {
+                    "type": "string",
+                    "enum": [
+                        "not_found",
+                        "database",
+                        "serialization",
+                        "bad_request",
+                        "unauthorized",
+                        "forbidden",
+                        "internal",
+                    ],
+                },
+            },
+        },
+    })
+}
+
+/// A code block count grouped by the given properties, which are all required
+fn code_count(keys: Value) -> Value {
+    let mut properties = match keys {
+        Value::Object(keys) => keys,
+        _ => Map::new(),
+    };
+    properties.insert("blocks".to_owned(), json!({ "type": "integer" }));
+    properties.insert("lines".to_owned(), json!({ "type": "integer" }));
+    let required = properties.keys().cloned().collect::<Vec<_>>();
+    json!({
+        "type": "object",
+        "required": required,
+        "properties": properties,
+    })
+}
+
+fn filter_parameters() -> Value {
+    json!({
+        "guild": query_parameter("guild", "Comma separated guild ids"),
+        "channel": query_parameter(
+            "channel",
+            "Comma separated channel ids, including messages in their threads",
+        ),
+        "since": query_parameter(
+            "since",
+            "Earliest message time, as a date, RFC 3339 time or unix timestamp",
+        ),
+        "until": query_parameter(
+            "until",
+            "End of the time range, dates include the whole day",
+        ),
+    })
+}
+
+fn query_parameter(name: &str, description: &str) -> Value {
+    json!({
+        "name": name,
+        "in": "query",
+        "required": false,
+        "description": description,
+        "schema": { "type": "string" },
+    })
+}
+
+fn schema_ref(name: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", name) })
+}
+
+fn array_of(name: &str) -> Value {
+    json!({ "type": "array", "items": schema_ref(name) })
+}
+
+#[cfg(test)]
+mod tests {
+    use iron::response::WriteBody;
+    use serde::Serialize;
+    use serde_json::{json, Value};
+
+    use super::{document, endpoints, DEFAULT_SESSION_GAP};
+    use crate::api::*;
+    use crate::error::{ApiError, AuthError, StoreError};
+    use crate::events::LiveEvent;
+    use crate::graph::{GraphDocument, GraphEdge, GraphFormat, GraphNode};
+
+    /// Checks that `value` has the shape `schema` describes, returning the first difference
+    ///
+    /// Objects may not have properties missing from the schema or lack required ones.
+    fn check(document: &Value, schema: &Value, value: &Value, path: &str) -> Result<(), String> {
+        if let Some(reference) = schema["$ref"].as_str() {
+            let name = reference.trim_start_matches("#/components/schemas/");
+            let resolved = &document["components"]["schemas"][name];
+            if resolved.is_null() {
+                return Err(format!("{} refers to missing schema {}", path, reference));
+            }
+            return check(document, resolved, value, path);
+        }
+
+        if value.is_null() {
+            return if schema["nullable"] == json!(true) {
+                Ok(())
+            } else {
+                Err(format!("{} is null but not nullable", path))
+            };
+        }
+
+        let type_matches = match schema["type"].as_str() {
+            Some("object") => value.is_object(),
+            Some("array") => value.is_array(),
+            Some("string") => value.is_string(),
+            Some("integer") => value.is_i64() || value.is_u64(),
+            Some("number") => value.is_number(),
+            Some("boolean") => value.is_boolean(),
+            _ => return Err(format!("{} has no supported type in {}", path, schema)),
+        };
+        if !type_matches {
+            return Err(format!(
+                "{} is not of type {}: {}",
+                path, schema["type"], value
+            ));
+        }
+        if let Some(allowed) = schema["enum"].as_array() {
+            if !allowed.contains(value) {
+                return Err(format!("{} is not one of {:?}: {}", path, allowed, value));
+            }
+        }
+
+        match value {
+            Value::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    check(
+                        document,
+                        &schema["items"],
+                        item,
+                        &format!("{}[{}]", path, i),
+                    )?;
+                }
+            }
+            Value::Object(fields) => {
+                if let Some(required) = schema["required"].as_array() {
+                    for key in required.iter().filter_map(Value::as_str) {
+                        if !fields.contains_key(key) {
+                            return Err(format!("{}.{} is required but missing", path, key));
+                        }
+                    }
+                }
+                // An object without properties, like this document, may contain anything
+                if let Some(properties) = schema["properties"].as_object() {
+                    for (key, field) in fields {
+                        let property = properties
+                            .get(key)
+                            .ok_or_else(|| format!("{}.{} is not documented", path, key))?;
+                        check(document, property, field, &format!("{}.{}", path, key))?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn check_schema<T: Serialize>(document: &Value, name: &str, value: &T) {
+        let schema = json!({ "$ref": format!("#/components/schemas/{}", name) });
+        let value = serde_json::to_value(value).expect("sample is serializable");
+        if let Err(e) = check(document, &schema, &value, name) {
+            panic!("{}", e);
+        }
+    }
+
+    fn user_count(name: Option<&str>) -> UserCount {
+        UserCount {
+            user_id: "1".to_owned(),
+            name: name.map(str::to_owned),
+            count: 2,
+        }
+    }
+
+    fn emoji_count(custom: bool) -> EmojiCount {
+        EmojiCount {
+            emoji: if custom { "ferris" } else { "👍" }.to_owned(),
+            id: if custom { Some("3".to_owned()) } else { None },
+            image_url: if custom {
+                Some("https://cdn.discordapp.com/emojis/3.png".to_owned())
+            } else {
+                None
+            },
+            count: 4,
+        }
+    }
+
+    /// A response of each endpoint, with optional fields both set and unset
+    fn responses() -> Vec<(&'static str, Value)> {
+        let count = || json!(Count { count: 5 });
+        let daily = || {
+            json!([DailyCount {
+                date: "2020-01-01".to_owned(),
+                public: 1,
+                private: 2,
+            }])
+        };
+
+        vec![
+            ("/total_msg_count", count()),
+            ("/msg_count", count()),
+            ("/edit_count", count()),
+            ("/total_msg_count_per_day", daily()),
+            ("/user_msg_count_per_day", daily()),
+            (
+                "/channels",
+                json!([
+                    ChannelInfo {
+                        channel_id: "1".to_owned(),
+                        guild_id: Some("2".to_owned()),
+                    },
+                    ChannelInfo {
+                        channel_id: "3".to_owned(),
+                        guild_id: None,
+                    },
+                ]),
+            ),
+            (
+                "/guilds",
+                json!([GuildInfo {
+                    guild_id: "1".to_owned(),
+                }]),
+            ),
+            (
+                "/sessions",
+                json!(SessionStats {
+                    gap_seconds: 1800,
+                    sessions: 2,
+                    median_length_seconds: 60,
+                    mean_length_seconds: 90.5,
+                    longest_seconds: 120,
+                    mean_messages: 3.5,
+                    starters: vec![user_count(Some("alice")), user_count(None)],
+                }),
+            ),
+            (
+                "/response_times",
+                json!([
+                    ResponseTimeInfo {
+                        user_id: "1".to_owned(),
+                        name: Some("alice".to_owned()),
+                        responses: 2,
+                        median_seconds: 30,
+                    },
+                    ResponseTimeInfo {
+                        user_id: "2".to_owned(),
+                        name: None,
+                        responses: 1,
+                        median_seconds: 45,
+                    },
+                ]),
+            ),
+            (
+                "/words",
+                json!(WordStats {
+                    messages: 2,
+                    average_words: 3.5,
+                    average_characters: 20.0,
+                    words: vec![TextCount {
+                        text: "rust".to_owned(),
+                        count: 3,
+                    }],
+                    bigrams: vec![TextCount {
+                        text: "rust code".to_owned(),
+                        count: 2,
+                    }],
+                    vocabulary: vec![VocabularyInfo {
+                        month: "2020-01".to_owned(),
+                        distinct: 10,
+                        new: 10,
+                        cumulative: 10,
+                    }],
+                    channels: vec![ChannelWordsInfo {
+                        channel_id: "1".to_owned(),
+                        words: vec![ScoredWord {
+                            text: "rust".to_owned(),
+                            score: 0.25,
+                        }],
+                    }],
+                }),
+            ),
+            (
+                "/emoji",
+                json!(EmojiStats {
+                    total: 8,
+                    emoji: vec![emoji_count(true), emoji_count(false)],
+                    users: vec![
+                        UserEmoji {
+                            user_id: "1".to_owned(),
+                            name: Some("alice".to_owned()),
+                            count: 4,
+                            top: vec![emoji_count(true)],
+                        },
+                        UserEmoji {
+                            user_id: "2".to_owned(),
+                            name: None,
+                            count: 4,
+                            top: vec![emoji_count(false)],
+                        },
+                    ],
+                    guilds: vec![GuildEmoji {
+                        guild_id: "1".to_owned(),
+                        count: 8,
+                        top: vec![emoji_count(true)],
+                    }],
+                    months: vec![MonthCount {
+                        month: "2020-01".to_owned(),
+                        count: 8,
+                    }],
+                }),
+            ),
+            (
+                "/code",
+                json!(CodeStats {
+                    blocks: 2,
+                    lines: 10,
+                    languages: vec![
+                        LanguageCode {
+                            language: Some("rust".to_owned()),
+                            blocks: 1,
+                            lines: 6,
+                        },
+                        LanguageCode {
+                            language: None,
+                            blocks: 1,
+                            lines: 4,
+                        },
+                    ],
+                    users: vec![
+                        UserCode {
+                            user_id: "1".to_owned(),
+                            name: Some("alice".to_owned()),
+                            blocks: 1,
+                            lines: 6,
+                        },
+                        UserCode {
+                            user_id: "2".to_owned(),
+                            name: None,
+                            blocks: 1,
+                            lines: 4,
+                        },
+                    ],
+                    channels: vec![ChannelCode {
+                        channel_id: "1".to_owned(),
+                        blocks: 2,
+                        lines: 10,
+                    }],
+                    months: vec![MonthCode {
+                        month: "2020-01".to_owned(),
+                        blocks: 2,
+                        lines: 10,
+                    }],
+                }),
+            ),
+            (
+                "/link_domains",
+                json!([DomainInfo {
+                    domain: "github.com".to_owned(),
+                    count: 3,
+                }]),
+            ),
+            (
+                "/top_links",
+                json!([LinkInfo {
+                    url: "https://github.com/".to_owned(),
+                    domain: "github.com".to_owned(),
+                    count: 3,
+                    first_shared: 1_577_836_800,
+                }]),
+            ),
+            (
+                "/links_per_day",
+                json!([DailyLinkCount {
+                    date: "2020-01-01".to_owned(),
+                    count: 3,
+                }]),
+            ),
+            (
+                "/interaction_graph",
+                json!(GraphDocument {
+                    nodes: vec![
+                        GraphNode {
+                            id: "1".to_owned(),
+                            name: Some("alice".to_owned()),
+                            messages: 4,
+                        },
+                        GraphNode {
+                            id: "2".to_owned(),
+                            name: None,
+                            messages: 2,
+                        },
+                    ],
+                    edges: vec![GraphEdge {
+                        source: "1".to_owned(),
+                        target: "2".to_owned(),
+                        weight: 3,
+                        mentions: 1,
+                        replies: 2,
+                    }],
+                }),
+            ),
+            (
+                "/status",
+                json!(Status {
+                    ok: true,
+                    collecting: true,
+                    gateway_connected: true,
+                    user: Some(StatusUser {
+                        id: "1".to_owned(),
+                        name: None,
+                    }),
+                    last_event: Some(1_577_836_800),
+                    tracked_channels: 3,
+                    schema_version: 5,
+                    database_size: None,
+                    database_writable: true,
+                    uptime_seconds: 60,
+                }),
+            ),
+            ("/openapi.json", document()),
+        ]
+    }
+
+    #[test]
+    fn responses_match_document() {
+        let document = document();
+        let responses = responses();
+
+        for (path, _, _, _) in endpoints() {
+            assert!(
+                responses.iter().any(|&(sampled, _)| sampled == path),
+                "no sample response for {}",
+                path
+            );
+        }
+        for (path, response) in &responses {
+            let schema = &document["paths"][*path]["get"]["responses"]["200"]["content"]
+                ["application/json"]["schema"];
+            if let Err(e) = check(&document, schema, response, path) {
+                panic!("{}", e);
+            }
+        }
+    }
+
+    #[test]
+    fn graph_formats_match_document() {
+        let document = document();
+        let operation = &document["paths"]["/interaction_graph"]["get"];
+
+        let format = operation["parameters"]
+            .as_array()
+            .and_then(|parameters| parameters.iter().find(|p| p["name"] == "format"))
+            .expect("format parameter is documented");
+        let formats = format["schema"]["enum"]
+            .as_array()
+            .expect("formats are listed");
+        for name in formats.iter().filter_map(Value::as_str) {
+            let format: GraphFormat = name.parse().expect("listed formats are supported");
+            assert!(
+                operation["responses"]["200"]["content"][format.content_type()].is_object(),
+                "no response content for format {}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn session_gap_is_documented() {
+        let document = document();
+
+        for path in &["/sessions", "/response_times"] {
+            let gap = document["paths"][*path]["get"]["parameters"]
+                .as_array()
+                .and_then(|parameters| parameters.iter().find(|p| p["name"] == "gap"))
+                .unwrap_or_else(|| panic!("{} does not document gap", path));
+            assert_eq!(gap["in"], "query");
+            assert_eq!(gap["schema"]["type"], "integer");
+            assert_eq!(
+                gap["schema"]["default"],
+                json!(DEFAULT_SESSION_GAP / 60),
+                "default gap of {} is not the one used",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn events_match_document() {
+        let document = document();
+
+        check_schema(
+            &document,
+            "Counters",
+            &Counters {
+                total: 3,
+                user: 2,
+                edits: 1,
+            },
+        );
+        let events = vec![
+            LiveEvent::Message {
+                message_id: "1".to_owned(),
+                channel_id: "2".to_owned(),
+                guild_id: Some("3".to_owned()),
+                author_id: "4".to_owned(),
+                time: 1_577_836_800,
+            },
+            LiveEvent::Edit {
+                message_id: "1".to_owned(),
+                channel_id: "2".to_owned(),
+                guild_id: None,
+            },
+            LiveEvent::Deletion {
+                message_id: "1".to_owned(),
+                channel_id: "2".to_owned(),
+                guild_id: None,
+            },
+        ];
+        for event in &events {
+            check_schema(&document, "LiveEvent", event);
+        }
+    }
+
+    #[test]
+    fn errors_match_document() {
+        let document = document();
+        let errors = vec![
+            ApiError::Store(StoreError::Sqlite(rusqlite::Error::QueryReturnedNoRows)),
+            ApiError::Store(StoreError::WriterStopped),
+            ApiError::Serialization(serde_json::from_str::<i64>("x").unwrap_err()),
+            ApiError::BadRequest("invalid".to_owned()),
+            ApiError::Auth(AuthError::Unauthorized),
+            ApiError::Auth(AuthError::Forbidden),
+            ApiError::Internal("failed".to_owned()),
+        ];
+
+        for err in &errors {
+            let mut body = Vec::new();
+            error_response(err)
+                .body
+                .expect("error responses have a body")
+                .write_body(&mut body)
+                .expect("body is written to memory");
+            let body: Value = serde_json::from_slice(&body).expect("error body is JSON");
+            check_schema(&document, "Error", &body);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+use console::{style, Key, Term};
+use log::warn;
+use serenity::model::id::{ChannelId, GuildId};
+use std::collections::HashSet;
+use std::io;
+
+use crate::discord::RestClient;
+use crate::event_handler::OneshotData;
+
+/// Most candidates shown at once
+const MAX_VISIBLE: usize = 15;
+
+/// A channel that can be picked for tracking
+pub struct Candidate {
+    pub label: String,
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+}
+
+/// Lists the guilds' text and forum channels and the private channels from the ready payload
+pub fn candidates(data: &OneshotData, rest: &RestClient) -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+
+    for (guild_id, guild_name) in data.guilds() {
+        let mut channels = match rest.text_channels(guild_id) {
+            Ok(channels) => channels,
+            Err(e) => {
+                warn!("Unable to fetch channels of {}: {}", guild_name, e);
+                continue;
+            }
+        };
+        channels.sort_by_key(|channel| channel.position);
+
+        candidates.extend(channels.into_iter().map(|channel| Candidate {
+            label: format!("{} #{}", guild_name, channel.name),
+            guild_id: Some(guild_id),
+            channel_id: channel.id,
+        }));
+    }
+
+    for (&channel_id, channel) in data.ready.private_channels.iter() {
+        candidates.push(Candidate {
+            label: format!("DM {}", channel),
+            guild_id: None,
+            channel_id,
+        });
+    }
+
+    candidates
+}
+
+/// Lets the user filter the candidates by typing and select some of them
+///
+/// Returns the indices of the selected candidates, or `None` if the picker was cancelled.
+pub fn pick(term: &Term, candidates: &[Candidate]) -> io::Result<Option<Vec<usize>>> {
+    let mut query = String::new();
+    let mut cursor = 0;
+    let mut selected = HashSet::new();
+    let mut filtered = filter(&query, candidates);
+    let mut drawn = 0;
+
+    loop {
+        clear(term, drawn)?;
+        drawn = render(term, &query, candidates, &filtered, cursor, &selected)?;
+
+        match term.read_key()? {
+            Key::Escape => {
+                clear(term, drawn)?;
+                return Ok(None);
+            }
+            Key::Enter => {
+                clear(term, drawn)?;
+                // Without a selection the highlighted candidate is picked
+                if selected.is_empty() {
+                    selected.extend(filtered.get(cursor));
+                }
+                let mut picked = selected.into_iter().collect::<Vec<_>>();
+                picked.sort();
+                return Ok(Some(picked));
+            }
+            Key::ArrowUp => cursor = cursor.saturating_sub(1),
+            Key::ArrowDown => {
+                if cursor + 1 < filtered.len() {
+                    cursor += 1;
+                }
+            }
+            Key::Char('\t') => {
+                if let Some(&index) = filtered.get(cursor) {
+                    if !selected.remove(&index) {
+                        selected.insert(index);
+                    }
+                }
+            }
+            Key::Backspace => {
+                query.pop();
+                filtered = filter(&query, candidates);
+                cursor = 0;
+            }
+            Key::Char(c) if !c.is_control() => {
+                query.push(c);
+                filtered = filter(&query, candidates);
+                cursor = 0;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Draws the picker and returns the number of lines written
+fn render(
+    term: &Term,
+    query: &str,
+    candidates: &[Candidate],
+    filtered: &[usize],
+    cursor: usize,
+    selected: &HashSet<usize>,
+) -> io::Result<usize> {
+    let (_, columns) = term.size();
+    // Long labels would wrap and leave lines behind when clearing
+    let width = (columns as usize).saturating_sub(7);
+
+    term.write_line(&format!("Filter: {}", query))?;
+
+    let visible = filtered.len().min(MAX_VISIBLE);
+    let offset = (cursor + 1).saturating_sub(visible);
+    for (row, &index) in filtered.iter().enumerate().skip(offset).take(visible) {
+        let check = if selected.contains(&index) {
+            "[x]"
+        } else {
+            "[ ]"
+        };
+        let label = candidates[index]
+            .label
+            .chars()
+            .take(width)
+            .collect::<String>();
+        if row == cursor {
+            term.write_line(&format!("> {} {}", check, style(label).bold()))?;
+        } else {
+            term.write_line(&format!("  {} {}", check, label))?;
+        }
+    }
+
+    term.write_line(&format!(
+        "{}/{} shown, {} selected (tab: select, enter: confirm, esc: cancel)",
+        filtered.len(),
+        candidates.len(),
+        selected.len()
+    ))?;
+
+    Ok(visible + 2)
+}
+
+fn clear(term: &Term, lines: usize) -> io::Result<()> {
+    // Moving the cursor by zero lines still moves it by one on some terminals
+    if lines > 0 {
+        term.clear_last_lines(lines)?;
+    }
+    Ok(())
+}
+
+/// Indices of the candidates matching `query`, best matches first
+fn filter(query: &str, candidates: &[Candidate]) -> Vec<usize> {
+    let mut matches = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(index, candidate)| {
+            fuzzy_score(query, &candidate.label).map(|score| (score, index))
+        })
+        .collect::<Vec<_>>();
+    // Stable, so equal scores keep the listing order
+    matches.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+    matches.into_iter().map(|(_, index)| index).collect()
+}
+
+/// Scores `label` if it contains the characters of `query` in order, ignoring case
+///
+/// Consecutive characters and matches close to the start of the label score higher.
+fn fuzzy_score(query: &str, label: &str) -> Option<i64> {
+    let mut label_chars = label.chars().flat_map(char::to_lowercase).enumerate();
+    let mut score = 0;
+    let mut first = None;
+    let mut last = None;
+
+    for c in query.chars().flat_map(char::to_lowercase) {
+        let (index, _) = label_chars.find(|&(_, label_char)| label_char == c)?;
+        score += match last {
+            Some(last) if last + 1 == index => 10,
+            _ => 1,
+        };
+        first = first.or(Some(index));
+        last = Some(index);
+    }
+
+    Some(score * 100 - first.unwrap_or(0) as i64)
+}
@@ -0,0 +1,436 @@
+use chrono::{Duration, NaiveDate};
+use serenity::model::id::UserId;
+use std::fmt::Write;
+
+use crate::analysis::emoji::{Emoji, EmojiAnalysis};
+use crate::analysis::words::WordAnalysis;
+use crate::error::ReportError;
+use crate::store::{Channel, Filter, StatsStore};
+
+/// Entries listed in each top list of the report
+const TOP_ENTRIES: usize = 10;
+
+/// A summary of one year of logged messages
+pub struct Report {
+    pub year: i32,
+    /// Name of the user the messages were recorded for
+    pub user_name: Option<String>,
+    pub total_messages: i64,
+    /// Messages sent by the user
+    pub user_messages: i64,
+    /// The day with the most logged messages and how many there were
+    pub busiest_day: Option<(String, i64)>,
+    pub top_channels: Vec<(Channel, i64)>,
+    /// Users the user shares direct messages with, by messages in those channels
+    pub dm_partners: Vec<(UserId, Option<String>, i64)>,
+    pub longest_streak: Option<Streak>,
+    pub top_words: Vec<(String, usize)>,
+    pub top_emoji: Vec<(Emoji, usize)>,
+    pub edits: i64,
+    pub deletions: i64,
+}
+
+/// Consecutive days on which the user sent messages
+#[derive(Clone, Copy)]
+pub struct Streak {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub days: i64,
+}
+
+impl Report {
+    /// Edits per hundred messages
+    pub fn edit_rate(&self) -> f64 {
+        rate(self.edits, self.total_messages)
+    }
+
+    /// Deletions per hundred messages
+    pub fn deletion_rate(&self) -> f64 {
+        rate(self.deletions, self.total_messages)
+    }
+}
+
+/// Computes the report of `year` from the store
+pub fn build(
+    stats: &StatsStore,
+    year: i32,
+    stopwords: &Option<Vec<String>>,
+) -> Result<Report, ReportError> {
+    let (since, until) = match (new_year(year), year.checked_add(1).and_then(new_year)) {
+        (Some(since), Some(until)) => (since, until),
+        _ => return Err(ReportError::YearOutOfRange(year)),
+    };
+    let filter = Filter {
+        since: Some(since),
+        until: Some(until),
+        ..Filter::default()
+    };
+
+    let busiest_day = stats
+        .get_total_msgs_per_day(&filter)?
+        .into_iter()
+        .map(|day| (day.date, day.public + day.private))
+        .max_by_key(|&(_, count)| count);
+
+    let names = stats.get_user_names()?;
+    let dm_partners = stats
+        .get_dm_partners(&filter, TOP_ENTRIES)?
+        .into_iter()
+        .map(|(user_id, count)| (user_id, names.get(&user_id).cloned(), count))
+        .collect();
+
+    let mut words = WordAnalysis::new(stopwords);
+    let mut emoji = EmojiAnalysis::new();
+    stats.for_each_content(&filter, |msg| {
+        words.add(&msg);
+        emoji.add(&msg);
+    })?;
+
+    Ok(Report {
+        year,
+        user_name: stats.current_user()?.and_then(|(_, name)| name),
+        total_messages: stats.get_msg_count(&filter)?,
+        user_messages: stats.get_user_msg_count(&filter)?,
+        busiest_day,
+        top_channels: stats.get_channel_msg_counts(&filter, TOP_ENTRIES)?,
+        dm_partners,
+        longest_streak: longest_streak(&stats.get_user_active_days(&filter)?),
+        top_words: words.top_words(TOP_ENTRIES),
+        top_emoji: emoji.top(TOP_ENTRIES),
+        edits: stats.get_edit_count(&filter)?,
+        deletions: stats.get_deletion_count(&filter)?,
+    })
+}
+
+/// Midnight UTC of the first day of `year` as a unix timestamp
+fn new_year(year: i32) -> Option<i64> {
+    NaiveDate::from_ymd_opt(year, 1, 1).map(|day| day.and_hms(0, 0, 0).timestamp())
+}
+
+/// The longest run of consecutive days in an ascending list of `YYYY-MM-DD` dates
+fn longest_streak(days: &[String]) -> Option<Streak> {
+    let mut longest: Option<Streak> = None;
+    let mut current: Option<Streak> = None;
+
+    for day in days {
+        let day = match NaiveDate::parse_from_str(day, "%Y-%m-%d") {
+            Ok(day) => day,
+            Err(_) => continue,
+        };
+        current = Some(match current {
+            Some(streak) if streak.end + Duration::days(1) == day => Streak {
+                end: day,
+                days: streak.days + 1,
+                ..streak
+            },
+            _ => Streak {
+                start: day,
+                end: day,
+                days: 1,
+            },
+        });
+
+        let streak = current.as_ref().expect("set above");
+        if longest
+            .as_ref()
+            .map_or(true, |longest| streak.days > longest.days)
+        {
+            longest = Some(*streak);
+        }
+    }
+
+    longest
+}
+
+fn rate(count: i64, total: i64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+/// Where Discord opens a channel, for linking channels whose names are not stored
+fn channel_url(channel: &Channel) -> String {
+    let guild = channel
+        .guild_id
+        .map_or_else(|| "@me".to_owned(), |guild_id| guild_id.0.to_string());
+    format!(
+        "https://discord.com/channels/{}/{}",
+        guild, channel.channel_id.0
+    )
+}
+
+fn channel_label(channel: &Channel) -> String {
+    match channel.guild_id {
+        Some(_) => format!("#{}", channel.channel_id.0),
+        None => format!("Direct messages {}", channel.channel_id.0),
+    }
+}
+
+fn user_label(user_id: UserId, name: &Option<String>) -> String {
+    name.clone().unwrap_or_else(|| user_id.0.to_string())
+}
+
+/// The report as a Markdown document
+pub fn markdown(report: &Report) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# {} in review\n", report.year);
+    if let Some(name) = &report.user_name {
+        let _ = writeln!(out, "Messages logged for {}.\n", md_escape(name));
+    }
+
+    let _ = writeln!(out, "## Messages\n");
+    let _ = writeln!(out, "- Logged messages: {}", report.total_messages);
+    let _ = writeln!(out, "- Sent by you: {}", report.user_messages);
+    if let Some((date, count)) = &report.busiest_day {
+        let _ = writeln!(out, "- Busiest day: {} with {} messages", date, count);
+    }
+    if let Some(streak) = &report.longest_streak {
+        let _ = writeln!(
+            out,
+            "- Longest streak: {} days, {} to {}",
+            streak.days, streak.start, streak.end
+        );
+    }
+    let _ = writeln!(
+        out,
+        "- Edits: {} ({:.1} per 100 messages)",
+        report.edits,
+        report.edit_rate()
+    );
+    let _ = writeln!(
+        out,
+        "- Deletions: {} ({:.1} per 100 messages)\n",
+        report.deletions,
+        report.deletion_rate()
+    );
+
+    md_table(
+        &mut out,
+        "Top channels",
+        ("Channel", "Messages"),
+        report.top_channels.iter().map(|(channel, count)| {
+            (
+                format!("[{}]({})", channel_label(channel), channel_url(channel)),
+                count.to_string(),
+            )
+        }),
+    );
+    md_table(
+        &mut out,
+        "Top direct message partners",
+        ("User", "Messages"),
+        report.dm_partners.iter().map(|(user_id, name, count)| {
+            (md_escape(&user_label(*user_id, name)), count.to_string())
+        }),
+    );
+    md_table(
+        &mut out,
+        "Most used words",
+        ("Word", "Uses"),
+        report
+            .top_words
+            .iter()
+            .map(|(word, count)| (md_escape(word), count.to_string())),
+    );
+    md_table(
+        &mut out,
+        "Most used emoji",
+        ("Emoji", "Uses"),
+        report.top_emoji.iter().map(|(emoji, count)| {
+            let emoji = match emoji.image_url() {
+                Some(url) => format!("![{}]({})", md_escape(emoji.name()), url),
+                None => emoji.name().to_owned(),
+            };
+            (emoji, count.to_string())
+        }),
+    );
+
+    out
+}
+
+fn md_table<I: Iterator<Item = (String, String)>>(
+    out: &mut String,
+    title: &str,
+    columns: (&str, &str),
+    rows: I,
+) {
+    let rows = rows.collect::<Vec<_>>();
+    if rows.is_empty() {
+        return;
+    }
+
+    let _ = writeln!(out, "## {}\n", title);
+    let _ = writeln!(out, "| {} | {} |\n| --- | ---: |", columns.0, columns.1);
+    for (label, count) in rows {
+        let _ = writeln!(out, "| {} | {} |", label, count);
+    }
+    out.push('\n');
+}
+
+fn md_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\`*_[]<>|#".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The report as a single HTML page without external resources other than emoji images
+pub fn html(report: &Report) -> String {
+    let mut body = String::new();
+
+    let mut numbers = vec![
+        (
+            "Logged messages".to_owned(),
+            report.total_messages.to_string(),
+        ),
+        ("Sent by you".to_owned(), report.user_messages.to_string()),
+    ];
+    if let Some((date, count)) = &report.busiest_day {
+        numbers.push((format!("Busiest day, {}", date), count.to_string()));
+    }
+    if let Some(streak) = &report.longest_streak {
+        numbers.push((
+            format!("Day streak, {} to {}", streak.start, streak.end),
+            streak.days.to_string(),
+        ));
+    }
+    numbers.push((
+        format!("Edits, {:.1} per 100 messages", report.edit_rate()),
+        report.edits.to_string(),
+    ));
+    numbers.push((
+        format!("Deletions, {:.1} per 100 messages", report.deletion_rate()),
+        report.deletions.to_string(),
+    ));
+
+    body.push_str("<section class=\"numbers\">\n");
+    for (label, value) in numbers {
+        let _ = writeln!(
+            body,
+            "<div><strong>{}</strong><span>{}</span></div>",
+            value,
+            html_escape(&label)
+        );
+    }
+    body.push_str("</section>\n");
+
+    html_table(
+        &mut body,
+        "Top channels",
+        ("Channel", "Messages"),
+        report.top_channels.iter().map(|(channel, count)| {
+            (
+                format!(
+                    "<a href=\"{}\">{}</a>",
+                    channel_url(channel),
+                    html_escape(&channel_label(channel))
+                ),
+                *count as usize,
+            )
+        }),
+    );
+    html_table(
+        &mut body,
+        "Top direct message partners",
+        ("User", "Messages"),
+        report.dm_partners.iter().map(|(user_id, name, count)| {
+            (html_escape(&user_label(*user_id, name)), *count as usize)
+        }),
+    );
+    html_table(
+        &mut body,
+        "Most used words",
+        ("Word", "Uses"),
+        report
+            .top_words
+            .iter()
+            .map(|(word, count)| (html_escape(word), *count)),
+    );
+    html_table(
+        &mut body,
+        "Most used emoji",
+        ("Emoji", "Uses"),
+        report.top_emoji.iter().map(|(emoji, count)| {
+            let emoji = match emoji.image_url() {
+                Some(url) => format!(
+                    "<img src=\"{}\" alt=\"{}\" title=\"{1}\">",
+                    url,
+                    html_escape(emoji.name())
+                ),
+                None => html_escape(emoji.name()),
+            };
+            (emoji, *count)
+        }),
+    );
+
+    let subtitle = match &report.user_name {
+        Some(name) => format!("<p>Messages logged for {}</p>\n", html_escape(name)),
+        None => String::new(),
+    };
+    format!(
+        "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"UTF-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>{year} in review</title>
+<style>{style}</style>
+</head>
+<body>
+<h1>{year} in review</h1>
+{subtitle}{body}</body>
+</html>
+",
+        year = report.year,
+        style = HTML_STYLE,
+        subtitle = subtitle,
+        body = body
+    )
+}
+
+fn html_table<I: Iterator<Item = (String, usize)>>(
+    out: &mut String,
+    title: &str,
+    columns: (&str, &str),
+    rows: I,
+) {
+    let rows = rows.collect::<Vec<_>>();
+    if rows.is_empty() {
+        return;
+    }
+
+    let _ = writeln!(
+        out,
+        "<h2>{}</h2>\n<table>\n<tr><th>{}</th><th>{}</th></tr>",
+        title, columns.0, columns.1
+    );
+    for (label, count) in rows {
+        let _ = writeln!(out, "<tr><td>{}</td><td>{}</td></tr>", label, count);
+    }
+    out.push_str("</table>\n");
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const HTML_STYLE: &str = "
+body { font-family: sans-serif; max-width: 48em; margin: 2em auto; padding: 0 1em; color: #222; }
+h1 { font-size: 2.5em; margin-bottom: 0.2em; }
+.numbers { display: flex; flex-wrap: wrap; gap: 1em; margin: 2em 0; }
+.numbers div { background: #eef0fb; border-radius: 0.5em; padding: 1em; min-width: 10em; }
+.numbers strong { display: block; font-size: 2em; color: #5865f2; }
+table { border-collapse: collapse; width: 100%; margin-bottom: 2em; }
+th, td { text-align: left; padding: 0.4em; border-bottom: 1px solid #ddd; }
+td:last-child, th:last-child { text-align: right; }
+img { height: 1.5em; vertical-align: middle; }
+";
@@ -1,140 +1,367 @@
-use crate::error::StoreError;
+use crate::discord::{RestClient, Thread, PAGE_SIZE};
+use crate::error::RestError;
 use crate::event_handler::OneshotData;
+use crate::metrics::METRICS;
+use crate::shutdown;
 use crate::store;
 use crate::store::StatsStore;
-use indicatif::{ProgressBar, ProgressStyle};
-use rusqlite::ErrorCode;
-use serenity::model::id::GuildId;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use log::{error, warn};
+use serenity::model::id::{GuildId, MessageId};
 use serenity::model::prelude::ChannelId;
-use std::collections::HashSet;
+use serenity::prelude::Mutex;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::Arc;
+use std::thread;
 
 pub struct MessageScanner {
     pub data: OneshotData,
     pub store: Arc<StatsStore>,
+    pub rest: Arc<RestClient>,
+    /// Number of channels scanned at the same time
+    pub jobs: usize,
+}
+
+/// Which messages of each channel are fetched, newest first
+#[derive(Clone, Copy, Debug)]
+pub struct ScanRange {
+    /// Most messages searched per channel, all in the range if `None`
+    pub max_count: Option<u64>,
+    /// Id of the oldest message to fetch, from `time::snowflake_at`
+    pub since: Option<MessageId>,
+    /// Only messages with lower ids are fetched
+    pub until: Option<MessageId>,
+}
+
+impl ScanRange {
+    fn contains(&self, message_id: MessageId) -> bool {
+        self.since.map_or(true, |since| message_id >= since)
+            && self.until.map_or(true, |until| message_id < until)
+    }
+}
+
+/// A channel waiting to be scanned and the progress bar showing it
+struct ChannelScan {
+    guild_id: Option<GuildId>,
+    channel_id: ChannelId,
+    /// The channel of a thread or forum post
+    parent_id: Option<ChannelId>,
+    name: String,
+    pb: ProgressBar,
+}
+
+/// Scans channels taken from a shared queue
+///
+/// Workers share one REST client, which retries requests that ran into Discord's rate limits.
+struct Worker {
+    rest: Arc<RestClient>,
+    store: Arc<StatsStore>,
+    queue: Arc<Mutex<VecDeque<ChannelScan>>>,
+    range: ScanRange,
 }
 
 impl MessageScanner {
-    pub fn scan_messages(&self, channels: &HashSet<store::Channel>, max_count: u64) {
-        let channels = channels
-            .iter()
-            .map(|channel| {
-                let channel_name;
-                if let Some(guild_id) = channel.guild_id {
-                    channel_name = guild_id
-                        .channels(&self.data.context.http)
-                        .ok()
-                        .and_then(|channels| channels.get(&channel.channel_id).cloned())
-                        .map(|channel| "#".to_owned() + &channel.name)
-                } else {
-                    channel_name = channel.channel_id.name(&self.data.context)
+    pub fn scan_messages(&self, channels: &HashSet<store::Channel>, range: ScanRange) {
+        let progress = MultiProgress::new();
+        let mut queue = VecDeque::new();
+        let mut guild_channels = HashMap::new();
+        let mut active_threads = HashMap::new();
+        for channel_to_scan in channels {
+            let channel_id = channel_to_scan.channel_id;
+            let guild_id = match channel_to_scan.guild_id {
+                Some(guild_id) => guild_id,
+                None => {
+                    // Direct message or group
+                    match self.private_channel_name(channel_id) {
+                        Some(channel_name) => queue.push_back(ChannelScan {
+                            guild_id: None,
+                            channel_id,
+                            parent_id: None,
+                            pb: progress.add(new_progress_bar(range.max_count, &channel_name)),
+                            name: channel_name,
+                        }),
+                        None => warn!("Error getting private channel {:?}", channel_id),
+                    }
+                    continue;
                 }
-                (
-                    channel_name.unwrap_or_else(|| channel.channel_id.0.to_string()),
-                    channel,
-                )
-            })
-            .collect::<Vec<_>>();
+            };
 
-        let cache_clone = Arc::clone(&self.data.context.cache);
-        let cache = cache_clone.read();
-        let http = &self.data.context.http;
-        let msg_limit_per_call = max_count.min(100);
-        for (channel_name, channel_to_scan) in channels {
-            if let Some(guild_id) = channel_to_scan.guild_id {
-                // Guild Channel
-                if let Ok(guild_channels) = guild_id.channels(http) {
-                    if let Some(channel) = guild_channels.get(&channel_to_scan.channel_id) {
-                        self.scan_channel(
-                            max_count,
-                            msg_limit_per_call,
-                            Some(guild_id),
-                            channel.id,
-                            &channel_name,
-                        );
-                    } else {
-                        eprintln!(
-                            "Error getting guild {:?} channel {:?}",
-                            guild_id, channel_to_scan.channel_id,
-                        )
+            let listed = guild_channels.entry(guild_id).or_insert_with(|| {
+                match self.rest.text_channels(guild_id) {
+                    Ok(channels) => channels,
+                    Err(e) => {
+                        warn!("Unable to fetch channels of guild {}: {}", guild_id.0, e);
+                        Vec::new()
                     }
                 }
-            } else {
-                // Direct message
-                if let Some(channel) = cache.private_channels.get(&channel_to_scan.channel_id) {
-                    self.scan_channel(
-                        max_count,
-                        msg_limit_per_call,
-                        None,
-                        channel.read().id,
-                        &channel_name,
+            });
+            let channel = match listed.iter().find(|channel| channel.id == channel_id) {
+                Some(channel) => channel.clone(),
+                None => {
+                    warn!(
+                        "Error getting guild {:?} channel {:?}",
+                        guild_id, channel_id
                     );
-                } else {
-                    eprintln!("Error getting channel {:?}", channel_to_scan.channel_id,)
+                    continue;
                 }
+            };
+
+            // Threads and forum posts are scanned after the channel they belong to
+            let channel_name = format!("#{}", channel.name);
+            let threads = self.threads(guild_id, channel_id, &mut active_threads);
+            if !channel.forum {
+                queue.push_back(ChannelScan {
+                    guild_id: Some(guild_id),
+                    channel_id,
+                    parent_id: None,
+                    pb: progress.add(new_progress_bar(range.max_count, &channel_name)),
+                    name: channel_name.clone(),
+                });
+            }
+            for thread in threads {
+                let thread_name = format!("{} › {}", channel_name, thread.name);
+                queue.push_back(ChannelScan {
+                    guild_id: Some(guild_id),
+                    channel_id: thread.id,
+                    parent_id: Some(channel_id),
+                    pb: progress.add(new_progress_bar(range.max_count, &thread_name)),
+                    name: thread_name,
+                });
             }
         }
+
+        let queue = Arc::new(Mutex::new(queue));
+        let workers = (0..self.jobs.max(1))
+            .map(|_| {
+                let worker = Worker {
+                    rest: Arc::clone(&self.rest),
+                    store: Arc::clone(&self.store),
+                    queue: Arc::clone(&queue),
+                    range,
+                };
+                thread::spawn(move || worker.run())
+            })
+            .collect::<Vec<_>>();
+
+        // Draws the bars until every one of them is finished
+        if let Err(e) = progress.join() {
+            error!("Unable to draw progress: {:?}", e);
+        }
+        for worker in workers {
+            if worker.join().is_err() {
+                error!("A scan worker panicked");
+            }
+        }
+    }
+
+    /// Name of a direct message or group from the cache
+    fn private_channel_name(&self, channel_id: ChannelId) -> Option<String> {
+        {
+            let cache = self.data.context.cache.read();
+            if !cache.private_channels.contains_key(&channel_id)
+                && !cache.groups.contains_key(&channel_id)
+            {
+                return None;
+            }
+        }
+        Some(
+            channel_id
+                .name(&self.data.context)
+                .unwrap_or_else(|| channel_id.0.to_string()),
+        )
     }
 
-    fn scan_channel(
+    /// The active and archived threads or forum posts of a channel
+    ///
+    /// Active threads are listed per guild, so they are fetched once for all of its channels.
+    fn threads(
         &self,
-        max_count: u64,
-        msg_limit_per_call: u64,
-        guild_id: Option<GuildId>,
-        channel: ChannelId,
-        channel_name: &str,
-    ) {
-        let mut searched = 0;
-        let mut collected = 0;
-        let mut last_msg = None;
-        let http = &self.data.context.http;
-
-        let pb = ProgressBar::new(max_count);
-        pb.set_style(
-            ProgressStyle::default_bar()
-                .progress_chars("##-")
-                .template(" {msg} {wide_bar} {pos}/{len} "),
-        );
-
-        pb.set_message(&channel_name);
-
-        while searched < max_count {
-            let msgs = if let Some(last) = last_msg {
-                channel.messages(http, |retriever| {
-                    retriever.limit(msg_limit_per_call).before(last)
-                })
-            } else {
-                channel.messages(http, |retriever| retriever.limit(msg_limit_per_call))
-            };
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        active_threads: &mut HashMap<GuildId, Vec<Thread>>,
+    ) -> Vec<Thread> {
+        let active = active_threads.entry(guild_id).or_insert_with(|| {
+            match self.rest.active_threads(guild_id) {
+                Ok(threads) => threads,
+                Err(e) => {
+                    warn!("Unable to fetch threads of guild {}: {}", guild_id.0, e);
+                    Vec::new()
+                }
+            }
+        });
+        let mut threads = active
+            .iter()
+            .filter(|thread| thread.parent_id == channel_id)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        match self.rest.archived_threads(channel_id) {
+            Ok(archived) => {
+                let mut seen = threads
+                    .iter()
+                    .map(|thread| thread.id)
+                    .collect::<HashSet<_>>();
+                threads.extend(archived.into_iter().filter(|thread| seen.insert(thread.id)));
+            }
+            Err(e) => warn!(
+                "Unable to fetch archived threads of channel {}: {}",
+                channel_id.0, e
+            ),
+        }
+        threads
+    }
+}
+
+fn new_progress_bar(max_count: Option<u64>, channel_name: &str) -> ProgressBar {
+    let pb = match max_count {
+        Some(max_count) => {
+            let pb = ProgressBar::new(max_count);
+            pb.set_style(
+                ProgressStyle::default_bar()
+                    .progress_chars("##-")
+                    .template(" {msg} {wide_bar} {pos}/{len} "),
+            );
+            pb
+        }
+        // The number of messages in a time range is not known up front
+        None => {
+            let pb = ProgressBar::new_spinner();
+            pb.set_style(ProgressStyle::default_spinner().template(" {spinner} {msg} {pos} "));
+            pb
+        }
+    };
+    pb.set_message(&format!("{} (queued)", channel_name));
+    pb
+}
+
+impl Worker {
+    fn run(&self) {
+        loop {
+            let scan = self.queue.lock().pop_front();
+            match scan {
+                // Queued channels are still taken after a shutdown, so every bar is finished
+                Some(scan) if shutdown::requested() => scan.pb.abandon(),
+                Some(scan) => {
+                    // An unfinished bar would keep `MultiProgress::join` waiting forever
+                    let scanned =
+                        panic::catch_unwind(AssertUnwindSafe(|| self.scan_channel(&scan)));
+                    match scanned {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => error!("Error fetching messages of {}: {}", scan.name, e),
+                        Err(_) => {
+                            error!("Scan of {} panicked", scan.name);
+                            scan.pb.abandon();
+                        }
+                    }
+                }
+                None => return,
+            }
+        }
+    }
+
+    /// Scans a channel until the end of the range
+    ///
+    /// A scan that is shut down or fails to fetch a page saves where it stopped, so the next one
+    /// continues from there.
+    fn scan_channel(&self, scan: &ChannelScan) -> Result<(), RestError> {
+        let ChannelScan {
+            guild_id,
+            channel_id: channel,
+            parent_id,
+            name: ref channel_name,
+            ref pb,
+        } = *scan;
+        let range = self.range;
+        let msg_limit_per_call = range.max_count.unwrap_or(PAGE_SIZE).min(PAGE_SIZE);
+
+        pb.set_message(channel_name);
+
+        // Continue where a scan that was shut down stopped, if it was in the same range
+        let (mut last_msg, mut searched) = match self.store.get_scan_cursor(channel) {
+            Ok(Some((before, searched))) if range.contains(before) => (Some(before), searched),
+            Ok(_) => (range.until, 0),
+            Err(e) => {
+                warn!("Unable to load scan cursor of {}: {:?}", channel_name, e);
+                (range.until, 0)
+            }
+        };
+        let mut collected = searched;
+        let (mut inserted, mut skipped, mut edited) = (0, 0, 0);
+        pb.set_position(searched);
+
+        while range
+            .max_count
+            .map_or(true, |max_count| searched < max_count)
+        {
+            if shutdown::requested() {
+                self.save_cursor(scan, last_msg, searched);
+                pb.abandon();
+                return Ok(());
+            }
+
+            let msgs = self.rest.messages(channel, last_msg, msg_limit_per_call);
 
             match msgs {
                 Ok(mut msgs) => {
                     if msgs.is_empty() {
                         break;
                     };
+                    last_msg = msgs.last().map(|msg| msg.message_id);
+                    // Pages are newest first, so the rest of the channel is older than `since`
+                    let reached_since = msgs.iter().any(|msg| !range.contains(msg.message_id));
+                    msgs.retain(|msg| range.contains(msg.message_id));
+
                     collected += msgs.len() as u64;
+                    METRICS.scanned_messages.add(msgs.len() as u64);
                     for msg in &mut msgs {
-                        // why is this necessary?
+                        // Messages fetched from a channel do not include its guild
                         msg.guild_id = guild_id;
-                        // Ignore success and UNIQUE constraint errors
-                        match self.store.insert_msg(msg) {
-                            Ok(_rows) => {}
-                            Err(StoreError::Sqlite(rusqlite::Error::SqliteFailure(e, _)))
-                                if e.code == ErrorCode::ConstraintViolation => {}
-                            err @ _ => pb.println(format!("Unable to insert message: {:?}", err)),
-                        };
+                        msg.parent_channel_id = parent_id;
+                    }
+                    match self.store.insert_msgs(&msgs) {
+                        Ok(count) => {
+                            inserted += count.inserted;
+                            skipped += count.skipped;
+                            edited += count.edited;
+                            pb.set_message(&format!(
+                                "{} ({} new, {} already stored, {} edited)",
+                                channel_name, inserted, skipped, edited
+                            ));
+                        }
+                        Err(e) => warn!("Unable to insert messages: {:?}", e),
+                    }
+                    if reached_since {
+                        pb.inc(msgs.len() as u64);
+                        break;
                     }
-                    last_msg = msgs.last().cloned();
                 }
                 Err(e) => {
-                    pb.println(format!("Error fetching messages: {:#?}", e));
-                    break;
+                    METRICS.scan_fetch_errors.inc();
+                    self.save_cursor(scan, last_msg, searched);
+                    pb.abandon();
+                    return Err(e);
                 }
             };
             pb.inc(msg_limit_per_call);
             searched += msg_limit_per_call;
         }
+        if let Err(e) = self.store.clear_scan_cursor(channel) {
+            warn!("Unable to clear scan cursor of {}: {:?}", channel_name, e);
+        }
         pb.set_length(collected);
         pb.finish();
+        Ok(())
+    }
+
+    fn save_cursor(&self, scan: &ChannelScan, before: Option<MessageId>, searched: u64) {
+        if let Some(before) = before {
+            if let Err(e) = self
+                .store
+                .set_scan_cursor(scan.channel_id, before, searched)
+            {
+                error!("Unable to save scan cursor of {}: {:?}", scan.name, e);
+            }
+        }
     }
 }
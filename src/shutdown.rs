@@ -0,0 +1,32 @@
+use log::{info, warn};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether SIGINT or SIGTERM was received
+///
+/// Long running loops check this to stop at a point where their progress can be saved.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Runs `on_signal` on the first SIGINT or SIGTERM, a second one exits immediately
+///
+/// Can only be installed once per process.
+pub fn install<F>(on_signal: F)
+where
+    F: Fn() + Send + 'static,
+{
+    let result = ctrlc::set_handler(move || {
+        if REQUESTED.swap(true, Ordering::SeqCst) {
+            warn!("Received a second signal, exiting without cleaning up");
+            std::process::exit(130);
+        }
+        info!("Shutting down, send the signal again to exit immediately");
+        on_signal();
+    });
+
+    if let Err(e) = result {
+        warn!("Unable to install signal handler: {:?}", e);
+    }
+}
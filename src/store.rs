@@ -1,15 +1,22 @@
-use rusqlite::{ToSql, NO_PARAMS};
+use log::warn;
+use rusqlite::types::Value;
+use rusqlite::{OpenFlags, OptionalExtension, ToSql, NO_PARAMS};
 use serenity::model::event::MessageUpdateEvent;
-use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use serenity::model::id::{ChannelId, GuildId, MessageId, RoleId, UserId};
 use serenity::{model::channel::Message, prelude::Mutex};
 use std::cell::RefCell;
-use std::path::Path;
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 
+use crate::analysis::links;
+use crate::connection::{self, ReadPool, Writer};
 use crate::error::StoreError;
 
+/// The database, written by a single writer thread and read through a pool of connections
 pub struct StatsStore {
-    conn: Arc<Mutex<rusqlite::Connection>>,
+    path: PathBuf,
+    writer: Writer,
+    readers: ReadPool,
     current_user: Mutex<RefCell<Option<UserId>>>,
 }
 
@@ -19,6 +26,161 @@ pub struct Channel {
     pub guild_id: Option<GuildId>,
 }
 
+/// Restricts aggregate queries to a subset of the stored messages
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    /// Only include messages from these guilds, which excludes direct messages
+    pub guilds: Option<Vec<GuildId>>,
+    pub channels: Option<Vec<ChannelId>>,
+    /// Unix timestamp of the earliest included message
+    pub since: Option<i64>,
+    /// Unix timestamp after the last included message
+    pub until: Option<i64>,
+}
+
+impl Filter {
+    pub fn is_empty(&self) -> bool {
+        self.guilds.is_none()
+            && self.channels.is_none()
+            && self.since.is_none()
+            && self.until.is_none()
+    }
+
+    /// SQL condition on the `Messages` table and the parameters it binds
+    fn messages_condition(&self) -> (String, Vec<Value>) {
+        let mut conditions = vec!["1".to_owned()];
+        let mut params = Vec::new();
+
+        if let Some(ref guilds) = self.guilds {
+            let placeholders = vec!["?"; guilds.len()].join(", ");
+            conditions.push(format!("GuildId IN ({})", placeholders));
+            params.extend(guilds.iter().map(|g| Value::Text(g.0.to_string())));
+        }
+        if let Some(ref channels) = self.channels {
+            // Messages in threads belong to the thread's channel as well
+            let placeholders = vec!["?"; channels.len()].join(", ");
+            conditions.push(format!(
+                "(ChannelId IN ({0}) OR ParentChannelId IN ({0}))",
+                placeholders
+            ));
+            for _ in 0..2 {
+                params.extend(channels.iter().map(|c| Value::Text(c.0.to_string())));
+            }
+        }
+        if let Some(since) = self.since {
+            conditions.push("Time >= ?".to_owned());
+            params.push(Value::Integer(since));
+        }
+        if let Some(until) = self.until {
+            conditions.push("Time < ?".to_owned());
+            params.push(Value::Integer(until));
+        }
+
+        (conditions.join(" AND "), params)
+    }
+
+    /// SQL condition for tables keyed by `MessageId` and `ChannelId` such as `Edits`
+    ///
+    /// Rows of messages that were never stored only match an empty filter.
+    fn related_condition(&self) -> (String, Vec<Value>) {
+        if self.is_empty() {
+            return ("1".to_owned(), Vec::new());
+        }
+
+        let (condition, params) = self.messages_condition();
+        (
+            format!(
+                "(MessageId, ChannelId) IN (SELECT MessageId, ChannelId FROM Messages WHERE {})",
+                condition
+            ),
+            params,
+        )
+    }
+}
+
+/// Messages sent on one day, split into guild and direct messages
+#[derive(Debug)]
+pub struct DailyMessageCount {
+    pub date: String,
+    pub public: i64,
+    pub private: i64,
+}
+
+#[derive(Debug)]
+pub struct DomainCount {
+    pub domain: String,
+    pub count: i64,
+}
+
+/// A link and how often it was shared
+#[derive(Debug)]
+pub struct SharedLink {
+    pub url: String,
+    pub domain: String,
+    pub count: i64,
+    /// Unix timestamp of the first message with the link
+    pub first_shared: i64,
+}
+
+/// A message reduced to where, when and by whom it was sent
+#[derive(Clone, Copy, Debug)]
+pub struct TimelineMessage {
+    pub channel_id: ChannelId,
+    pub author_id: UserId,
+    pub time: i64,
+}
+
+/// A message with its content, for statistics that look at the text
+#[derive(Debug)]
+pub struct MessageContent {
+    pub channel_id: ChannelId,
+    pub guild_id: Option<GuildId>,
+    pub author_id: UserId,
+    pub time: i64,
+    pub content: String,
+}
+
+/// Users as nodes and how they addressed each other as weighted edges
+#[derive(Debug, Default)]
+pub struct InteractionGraph {
+    pub users: Vec<GraphUser>,
+    pub interactions: Vec<Interaction>,
+}
+
+#[derive(Debug)]
+pub struct GraphUser {
+    pub user_id: UserId,
+    /// Last seen name, if the user was ever seen since names are recorded
+    pub name: Option<String>,
+    /// Messages matching the filter
+    pub messages: i64,
+}
+
+/// Messages from `source` addressed to `target`
+#[derive(Debug)]
+pub struct Interaction {
+    pub source: UserId,
+    pub target: UserId,
+    pub mentions: i64,
+    pub replies: i64,
+}
+
+impl Interaction {
+    pub fn weight(&self) -> i64 {
+        self.mentions + self.replies
+    }
+}
+
+/// Outcome of a batched insert
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InsertCount {
+    pub inserted: u64,
+    /// Rows that were already stored
+    pub skipped: u64,
+    /// Skipped messages whose content changed since they were stored
+    pub edited: u64,
+}
+
 #[derive(Debug)]
 pub struct StoreMessage {
     pub message_id: MessageId,
@@ -27,103 +189,525 @@ pub struct StoreMessage {
     pub channel_id: ChannelId,
     pub guild_id: Option<GuildId>,
     pub author_id: UserId,
+    /// The channel of the thread the message was sent in
+    pub parent_channel_id: Option<ChannelId>,
+}
+
+impl StoreMessage {
+    /// The channel the message counts for, that of its thread if it was sent in one
+    pub fn channel(&self) -> ChannelId {
+        self.parent_channel_id.unwrap_or(self.channel_id)
+    }
+}
+
+/// A message to be stored, from the gateway or the REST API
+#[derive(Clone, Debug)]
+pub struct NewMessage {
+    pub message_id: MessageId,
+    pub channel_id: ChannelId,
+    pub guild_id: Option<GuildId>,
+    /// The channel a thread or forum post belongs to, for messages sent in one
+    pub parent_channel_id: Option<ChannelId>,
+    pub author_id: UserId,
+    pub author_name: String,
+    pub time: i64,
+    pub edited_time: Option<i64>,
+    pub content: String,
+    /// Mentioned users and their names
+    pub mentions: Vec<(UserId, String)>,
+    pub mention_roles: Vec<RoleId>,
+    /// The message this one replies to
+    pub reply_to: Option<MessageId>,
+    /// Author of the message replied to, `None` if it was deleted
+    pub reply_to_author: Option<UserId>,
+}
+
+impl From<&Message> for NewMessage {
+    fn from(msg: &Message) -> Self {
+        NewMessage {
+            message_id: msg.id,
+            channel_id: msg.channel_id,
+            guild_id: msg.guild_id,
+            parent_channel_id: None,
+            author_id: msg.author.id,
+            author_name: msg.author.name.clone(),
+            time: msg.timestamp.timestamp(),
+            edited_time: msg.edited_timestamp.map(|edited| edited.timestamp()),
+            content: msg.content.clone(),
+            mentions: msg
+                .mentions
+                .iter()
+                .map(|user| (user.id, user.name.clone()))
+                .collect(),
+            mention_roles: msg.mention_roles.clone(),
+            // Not part of the gateway model, `ReplyResolver` looks them up
+            reply_to: None,
+            reply_to_author: None,
+        }
+    }
 }
 
 impl StatsStore {
     pub fn new(path: &Path) -> Result<StatsStore, StoreError> {
+        let mut conn = connection::open(path, OpenFlags::default())?;
+        // Read-only databases can still be served, just without concurrent writes
+        if let Err(e) = connection::enable_wal(&conn) {
+            warn!("Unable to enable write-ahead logging: {}", e);
+        }
+        StatsStore::migrate(&mut conn)?;
+        // Remember the user from the last connection so the web server can run on its own
+        let current_user = StatsStore::load_current_user(&conn)?;
+
         Ok(StatsStore {
-            conn: Arc::new(Mutex::new(StatsStore::setup_connection(path)?)),
-            current_user: Mutex::new(RefCell::new(None)),
+            path: path.to_owned(),
+            writer: Writer::new(conn),
+            readers: ReadPool::new(path),
+            current_user: Mutex::new(RefCell::new(current_user)),
         })
     }
 
-    fn setup_connection(path: &Path) -> Result<rusqlite::Connection, StoreError> {
-        let conn = rusqlite::Connection::open(path)?;
+    /// Applies the migrations newer than the database's `user_version`
+    fn migrate(conn: &mut rusqlite::Connection) -> Result<(), StoreError> {
+        let version: i64 = conn.query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))?;
+
+        for (index, statements) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+            let tx = conn.transaction()?;
+            for statement in statements.iter() {
+                tx.execute(statement, NO_PARAMS)?;
+            }
+            tx.execute_batch(&format!("PRAGMA user_version = {}", index + 1))?;
+            tx.commit()?;
+        }
+        Ok(())
+    }
+
+    pub fn schema_version(&self) -> Result<i64, StoreError> {
+        self.readers
+            .get()?
+            .query_row("PRAGMA user_version", NO_PARAMS, |row| row.get(0))
+            .map_err(Into::into)
+    }
+
+    /// Size of the database file and its write-ahead log in bytes
+    pub fn database_size(&self) -> std::io::Result<u64> {
+        let mut wal_path = self.path.clone().into_os_string();
+        wal_path.push("-wal");
+        let wal_size = std::fs::metadata(wal_path).map_or(0, |metadata| metadata.len());
+
+        std::fs::metadata(&self.path).map(|metadata| metadata.len() + wal_size)
+    }
+
+    /// Checks that the database file is not read-only and the writer thread is running
+    ///
+    /// Does not start a transaction, which could wait for the writer's lock.
+    pub fn is_writable(&self) -> bool {
+        let read_only = std::fs::metadata(&self.path)
+            .map(|metadata| metadata.permissions().readonly())
+            .unwrap_or(true);
 
-        conn.execute(CREATE_MSGS_TABLE_SQL, NO_PARAMS)?;
-        conn.execute(CREATE_EDITS_TABLE_SQL, NO_PARAMS)?;
-        conn.execute(CREATE_DELETIONS_TABLE_SQL, NO_PARAMS)?;
-        Ok(conn)
+        !read_only && self.writer.is_running()
     }
 
-    pub fn set_current_user(&self, user_id: UserId) {
-        *self.current_user.lock().get_mut() = Some(user_id)
+    /// Waits for pending writes and checkpoints the write-ahead log
+    ///
+    /// Called before exiting so nothing is left to recover on the next start.
+    pub fn close(&self) -> Result<(), StoreError> {
+        self.writer.checkpoint()
+    }
+
+    fn get_meta(conn: &rusqlite::Connection, key: &str) -> Result<Option<String>, StoreError> {
+        // language=sql
+        let query = "SELECT Value FROM Meta WHERE Key = ?";
+
+        match conn.query_row(query, &[key], |row| row.get(0)) {
+            Ok(value) => Ok(Some(value)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
     }
 
-    pub fn insert_msg(&self, msg: &Message) -> Result<usize, StoreError> {
+    fn set_meta(&self, key: &str, value: &str) -> Result<(), StoreError> {
+        // language=sql
+        let query = "INSERT OR REPLACE INTO Meta (Key, Value) VALUES (?, ?)";
+        let data = [key.to_owned(), value.to_owned()];
+
+        self.writer
+            .write(move |conn| conn.execute(query, &data).map(|_| ()).map_err(Into::into))
+    }
+
+    fn load_current_user(conn: &rusqlite::Connection) -> Result<Option<UserId>, StoreError> {
+        Ok(StatsStore::get_meta(conn, "CurrentUserId")?
+            .and_then(|id| id.parse::<u64>().ok())
+            .map(UserId))
+    }
+
+    pub fn set_current_user(&self, user_id: UserId, name: &str) -> Result<(), StoreError> {
+        *self.current_user.lock().get_mut() = Some(user_id);
+
+        self.set_meta("CurrentUserId", &user_id.0.to_string())?;
+        self.set_meta("CurrentUserName", name)
+    }
+
+    /// The user whose messages are recorded and their name, if they ever connected
+    pub fn current_user(&self) -> Result<Option<(UserId, Option<String>)>, StoreError> {
+        let user_id = match *self.current_user.lock().borrow() {
+            Some(user_id) => user_id,
+            None => return Ok(None),
+        };
+        let name = StatsStore::get_meta(&self.readers.get()?, "CurrentUserName")?;
+
+        Ok(Some((user_id, name)))
+    }
+
+    /// Where an interrupted history scan of a channel stopped and how many messages it searched
+    pub fn get_scan_cursor(
+        &self,
+        channel_id: ChannelId,
+    ) -> Result<Option<(MessageId, u64)>, StoreError> {
+        // language=sql
+        let query = "SELECT Before, Searched FROM ScanCursors WHERE ChannelId = ?";
+
+        let cursor = self
+            .readers
+            .get()?
+            .query_row(query, &[channel_id.0.to_string()], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            });
+        match cursor {
+            Ok((before, searched)) => Ok(before
+                .parse::<u64>()
+                .ok()
+                .map(|before| (MessageId(before), searched as u64))),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn set_scan_cursor(
+        &self,
+        channel_id: ChannelId,
+        before: MessageId,
+        searched: u64,
+    ) -> Result<(), StoreError> {
         // language=sql
         let query = "
-        INSERT INTO main.Messages
-        (MessageId, Time, Content, ChannelId, GuildId, AuthorId, Metadata)
-        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)";
+        INSERT OR REPLACE INTO ScanCursors (ChannelId, Before, Searched)
+        VALUES (?1, ?2, ?3)";
 
-        let data = &[
-            &(msg.id.0.to_string()) as &dyn ToSql,
-            &msg.timestamp.timestamp(),
-            &msg.content,
-            &(msg.channel_id.0.to_string()),
-            &msg.guild_id.map(|x| x.0.to_string()),
-            &(msg.author.id.0.to_string()),
-            &None::<String>,
-        ];
+        let data = (
+            channel_id.0.to_string(),
+            before.0.to_string(),
+            searched as i64,
+        );
 
-        Ok(self.conn.lock().execute(query, data)?)
+        self.writer.write(move |conn| {
+            conn.execute(query, &[&data.0 as &dyn ToSql, &data.1, &data.2])
+                .map(|_| ())
+                .map_err(Into::into)
+        })
     }
 
-    pub fn insert_edit(&self, update: &MessageUpdateEvent) -> Result<(), StoreError> {
+    pub fn clear_scan_cursor(&self, channel_id: ChannelId) -> Result<(), StoreError> {
+        // language=sql
+        let query = "DELETE FROM ScanCursors WHERE ChannelId = ?";
+        let data = [channel_id.0.to_string()];
+
+        self.writer
+            .write(move |conn| conn.execute(query, &data).map(|_| ()).map_err(Into::into))
+    }
+
+    pub fn insert_msg(&self, msg: &NewMessage) -> Result<usize, StoreError> {
         // language=sql
         let query = "
-        SELECT EditId, Times, EditContents FROM Edits WHERE MessageId = ?";
+        INSERT INTO main.Messages
+        (MessageId, Time, Content, ChannelId, GuildId, AuthorId, Metadata, ParentChannelId,
+         ReplyToId, ReplyToAuthorId)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)";
 
-        let q: rusqlite::Result<(i64, String, String)> =
-            self.conn
-                .lock()
-                .query_row(query, &[update.id.0.to_string()], |row| {
-                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
-                });
-        match q {
-            Ok((edit_id, ref time, ref content)) => {
-                let mut times: Vec<i64> = serde_json::from_str(time)?;
-                let mut edits: Vec<String> = serde_json::from_str(content)?;
+        let msg = msg.clone();
+        self.writer.write(move |conn| {
+            let rows = conn.execute(query, &StatsStore::message_params(&msg))?;
+            StatsStore::write_mentions(conn, &msg)?;
+            StatsStore::write_links(
+                conn,
+                &msg.message_id.0.to_string(),
+                &msg.channel_id.0.to_string(),
+                &msg.content,
+            )?;
+            Ok(rows)
+        })
+    }
+
+    /// Inserts a page of messages in one transaction, skipping those already stored
+    ///
+    /// Stored messages that were edited since are recorded as edits.
+    pub fn insert_msgs(&self, msgs: &[NewMessage]) -> Result<InsertCount, StoreError> {
+        // language=sql
+        let query = "
+        INSERT OR IGNORE INTO main.Messages
+        (MessageId, Time, Content, ChannelId, GuildId, AuthorId, Metadata, ParentChannelId,
+         ReplyToId, ReplyToAuthorId)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)";
+        // language=sql
+        let parent_query = "
+        UPDATE Messages SET ParentChannelId = ?1
+        WHERE MessageId = ?2 AND ChannelId = ?3 AND ParentChannelId IS NULL";
 
-                if let Some(ref timestamp) = update.timestamp {
-                    times.push(timestamp.timestamp())
+        let msgs = msgs.to_vec();
+        self.writer.write(move |conn| {
+            let mut stmt = conn.prepare_cached(query)?;
+            let mut count = InsertCount::default();
+            for msg in &msgs {
+                let inserted = stmt.execute(&StatsStore::message_params(msg))? > 0;
+                // Also for stored messages, which may predate recording mentions
+                StatsStore::write_mentions(conn, msg)?;
+                StatsStore::write_links(
+                    conn,
+                    &msg.message_id.0.to_string(),
+                    &msg.channel_id.0.to_string(),
+                    &msg.content,
+                )?;
+                if inserted {
+                    count.inserted += 1;
+                    continue;
+                }
+
+                count.skipped += 1;
+                // Messages stored live or before reply targets were recorded
+                StatsStore::record_reply(conn, msg)?;
+                // Thread messages stored before threads were recognized
+                if let Some(parent) = msg.parent_channel_id {
+                    conn.prepare_cached(parent_query)?.execute(&[
+                        parent.0.to_string(),
+                        msg.message_id.0.to_string(),
+                        msg.channel_id.0.to_string(),
+                    ])?;
+                }
+                if msg.edited_time.is_some() {
+                    let (id, channel) = (msg.message_id, msg.channel_id);
+                    if StatsStore::record_edit(conn, id, channel, msg.edited_time, &msg.content)? {
+                        count.edited += 1;
+                    }
                 }
-                if let Some(ref new_content) = update.content {
-                    edits.push(new_content.clone())
+            }
+            Ok(count)
+        })
+    }
+
+    /// Values for the columns of `Messages` in the order the inserts name them
+    fn message_params(msg: &NewMessage) -> [Value; 10] {
+        let text = |id: Option<u64>| id.map_or(Value::Null, |id| Value::Text(id.to_string()));
+        [
+            Value::Text(msg.message_id.0.to_string()),
+            Value::Integer(msg.time),
+            Value::Text(msg.content.clone()),
+            Value::Text(msg.channel_id.0.to_string()),
+            text(msg.guild_id.map(|id| id.0)),
+            Value::Text(msg.author_id.0.to_string()),
+            Value::Null,
+            text(msg.parent_channel_id.map(|id| id.0)),
+            text(msg.reply_to.map(|id| id.0)),
+            text(msg.reply_to_author.map(|id| id.0)),
+        ]
+    }
+
+    /// Records the reply targets of stored messages that have none yet
+    ///
+    /// Messages that are not stored or do not reply to anything are ignored.
+    pub fn set_reply_targets(&self, msgs: &[NewMessage]) -> Result<(), StoreError> {
+        let msgs = msgs
+            .iter()
+            .filter(|msg| msg.reply_to.is_some())
+            .cloned()
+            .collect::<Vec<_>>();
+        if msgs.is_empty() {
+            return Ok(());
+        }
+
+        self.writer.write(move |conn| {
+            for msg in &msgs {
+                StatsStore::record_reply(conn, msg)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn record_reply(conn: &rusqlite::Connection, msg: &NewMessage) -> Result<(), StoreError> {
+        // language=sql
+        let query = "
+        UPDATE Messages SET ReplyToId = ?1, ReplyToAuthorId = ?2
+        WHERE MessageId = ?3 AND ChannelId = ?4 AND ReplyToId IS NULL";
+
+        if let Some(reply_to) = msg.reply_to {
+            conn.prepare_cached(query)?.execute(&[
+                &reply_to.0.to_string() as &dyn ToSql,
+                &msg.reply_to_author.map(|id| id.0.to_string()),
+                &msg.message_id.0.to_string(),
+                &msg.channel_id.0.to_string(),
+            ])?;
+        }
+        Ok(())
+    }
+
+    /// Records who a message mentions and the names of the users involved
+    fn write_mentions(conn: &rusqlite::Connection, msg: &NewMessage) -> Result<(), StoreError> {
+        // language=sql
+        let mention_query = "
+        INSERT OR IGNORE INTO Mentions (MessageId, ChannelId, Kind, TargetId)
+        VALUES (?1, ?2, ?3, ?4)";
+        // language=sql
+        let user_query = "INSERT OR REPLACE INTO Users (UserId, Name) VALUES (?1, ?2)";
+
+        let mentions = msg
+            .mentions
+            .iter()
+            .map(|(user_id, _)| ("user", user_id.0))
+            .chain(msg.mention_roles.iter().map(|role| ("role", role.0)))
+            .chain(
+                channel_mentions(&msg.content)
+                    .into_iter()
+                    .map(|channel| ("channel", channel.0)),
+            );
+
+        let mut stmt = conn.prepare_cached(mention_query)?;
+        for (kind, target) in mentions {
+            stmt.execute(&[
+                &msg.message_id.0.to_string() as &dyn ToSql,
+                &msg.channel_id.0.to_string(),
+                &kind,
+                &target.to_string(),
+            ])?;
+        }
+
+        let mut stmt = conn.prepare_cached(user_query)?;
+        let author = (msg.author_id, msg.author_name.clone());
+        for (user_id, name) in std::iter::once(&author).chain(&msg.mentions) {
+            stmt.execute(&[&user_id.0.to_string(), name])?;
+        }
+        Ok(())
+    }
+
+    /// Records the links in a message's content, returns how many were new
+    fn write_links(
+        conn: &rusqlite::Connection,
+        message_id: &str,
+        channel_id: &str,
+        content: &str,
+    ) -> Result<usize, StoreError> {
+        // language=sql
+        let query = "
+        INSERT OR IGNORE INTO Links (MessageId, ChannelId, Url, Domain)
+        VALUES (?1, ?2, ?3, ?4)";
+
+        let mut stmt = conn.prepare_cached(query)?;
+        let mut inserted = 0;
+        for link in links::extract(content) {
+            inserted += stmt.execute(&[
+                message_id,
+                channel_id,
+                link.url.as_str(),
+                link.domain.as_str(),
+            ])?;
+        }
+        Ok(inserted)
+    }
+
+    /// Extracts links from all stored messages, for messages stored before links were
+    /// recorded or after the extraction changed. Returns how many links were added.
+    pub fn reindex_links(&self) -> Result<usize, StoreError> {
+        self.writer.write(|conn| {
+            // language=sql
+            let mut stmt = conn.prepare(
+                "SELECT MessageId, ChannelId, Content FROM Messages
+                WHERE Content LIKE '%http%://%'",
+            )?;
+            let mut rows = stmt.query(NO_PARAMS)?;
+            let mut inserted = 0;
+            while let Some(row) = rows.next()? {
+                let message_id: String = row.get(0)?;
+                let channel_id: String = row.get(1)?;
+                let content: String = row.get(2)?;
+                inserted += StatsStore::write_links(conn, &message_id, &channel_id, &content)?;
+            }
+            Ok(inserted)
+        })
+    }
+
+    /// Records an edit from the gateway, returns whether the content changed
+    pub fn insert_edit(&self, update: &MessageUpdateEvent) -> Result<bool, StoreError> {
+        // Updates without content only change embeds
+        let content = match update.content {
+            Some(ref content) => content.clone(),
+            None => return Ok(false),
+        };
+        let time = update.edited_timestamp.map(|t| t.timestamp());
+        let (message_id, channel_id) = (update.id, update.channel_id);
+
+        self.writer.write(move |conn| {
+            StatsStore::record_edit(conn, message_id, channel_id, time, &content)
+        })
+    }
+
+    /// Appends `content` to a message's edit history unless it is the latest known content
+    ///
+    /// Returns whether an edit was recorded.
+    fn record_edit(
+        conn: &rusqlite::Connection,
+        message_id: MessageId,
+        channel_id: ChannelId,
+        time: Option<i64>,
+        content: &str,
+    ) -> Result<bool, StoreError> {
+        let key = [message_id.0.to_string(), channel_id.0.to_string()];
+
+        // language=sql
+        let query = "
+        SELECT EditId, Times, EditContents FROM Edits WHERE MessageId = ?1 AND ChannelId = ?2";
+
+        let existing = conn
+            .query_row(query, &key, |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })
+            .optional()?;
+
+        match existing {
+            Some((edit_id, times, contents)) => {
+                // Older versions stored `null` for edits without content
+                let mut times: Vec<Option<i64>> = serde_json::from_str(&times)?;
+                let mut edits: Vec<String> =
+                    serde_json::from_str::<Option<_>>(&contents)?.unwrap_or_default();
+                if edits.last().map(String::as_str) == Some(content) {
+                    return Ok(false);
                 }
+                times.push(time);
+                edits.push(content.to_owned());
 
-                // Update existing row
                 // language=sql
                 let query = "
                 UPDATE Edits SET Times = ?, EditContents = ? WHERE EditId = ?";
 
                 let data = &[
-                    &serde_json::to_string(&times).unwrap() as &dyn ToSql,
-                    &serde_json::to_string(&edits).unwrap(),
+                    &serde_json::to_string(&times)? as &dyn ToSql,
+                    &serde_json::to_string(&edits)?,
                     &edit_id,
                 ];
-                self.conn.lock().execute(query, data)?;
+                conn.execute(query, data)?;
             }
-            Err(rusqlite::Error::QueryReturnedNoRows) => {
-                // Insert new edit row
-                let time = update.edited_timestamp.map(|t| t.timestamp());
-                let serialized_time = serde_json::to_string(&vec![time]).unwrap();
-                let content =
-                    serde_json::to_string(&update.content.as_ref().map(|c| vec![c.clone()]))
-                        .unwrap();
-
-                // Try and find the original content
+            None => {
+                // language=sql
                 let find_msg_query = "
-                SELECT Content FROM Messages WHERE MessageId == ?
-                ";
-                let orig_content: Option<String> = self
-                    .conn
-                    .lock()
-                    .query_row(find_msg_query, &[&update.id.0.to_string()], |row| {
-                        row.get(0)
-                    })
-                    .ok();
+                SELECT Content FROM Messages WHERE MessageId = ?1 AND ChannelId = ?2";
+
+                let orig_content: Option<String> = conn
+                    .query_row(find_msg_query, &key, |row| row.get(0))
+                    .optional()?;
+                if orig_content.as_ref().map(String::as_str) == Some(content) {
+                    return Ok(false);
+                }
 
                 // language=sql
                 let query = "
@@ -131,20 +715,16 @@ impl StatsStore {
                 VALUES (?1, ?2, ?3, ?4, ?5)";
 
                 let data = &[
-                    &update.id.0.to_string() as &dyn ToSql,
-                    &update.channel_id.0.to_string(),
-                    &serialized_time,
+                    &key[0] as &dyn ToSql,
+                    &key[1],
+                    &serde_json::to_string(&[time])?,
                     &orig_content,
-                    &content,
+                    &serde_json::to_string(&[content])?,
                 ];
-
-                self.conn.lock().execute(query, data)?;
-            }
-            err @ Err(_) => {
-                err?;
+                conn.execute(query, data)?;
             }
         };
-        Ok(())
+        Ok(true)
     }
 
     pub fn insert_deletion(
@@ -157,17 +737,17 @@ impl StatsStore {
         INSERT into Deletions (MessageId, ChannelId, Time)
         VALUES (?1, ?2, ?3)";
 
-        let data = &[
-            &message_id.0.to_string() as &dyn ToSql,
-            &channel_id.0.to_string(),
-            &chrono::offset::Utc::now().timestamp(),
-        ];
+        let data = (
+            message_id.0.to_string(),
+            channel_id.0.to_string(),
+            chrono::offset::Utc::now().timestamp(),
+        );
 
-        self.conn
-            .lock()
-            .execute(query, data)
-            .map(|_| ())
-            .map_err(Into::into)
+        self.writer.write(move |conn| {
+            conn.execute(query, &[&data.0 as &dyn ToSql, &data.1, &data.2])
+                .map(|_| ())
+                .map_err(Into::into)
+        })
     }
 
     pub fn get_message_with_channel_id(
@@ -177,13 +757,13 @@ impl StatsStore {
     ) -> Result<StoreMessage, StoreError> {
         // language=sql
         let query = "
-        SELECT MessageId, Time, Content, ChannelId, GuildId, AuthorId
+        SELECT MessageId, Time, Content, ChannelId, GuildId, AuthorId, ParentChannelId
         FROM Messages
         WHERE ChannelId = ?1
         AND MessageId = ?2
         ";
 
-        let conn = self.conn.lock();
+        let conn = self.readers.get()?;
         // TODO: figure out error handling here
         conn.query_row(
             query,
@@ -209,6 +789,10 @@ impl StatsStore {
                     .parse::<u64>()
                     .expect("invalid author_id in db")
                     .into();
+                let parent_channel_id = row
+                    .get::<_, Option<String>>(6)?
+                    .and_then(|id| id.parse().ok())
+                    .map(ChannelId);
                 Ok(StoreMessage {
                     message_id,
                     time: row.get(1)?,
@@ -216,101 +800,157 @@ impl StatsStore {
                     channel_id,
                     guild_id,
                     author_id,
+                    parent_channel_id,
                 })
             },
         )
         .map_err(Into::into)
     }
 
-    pub fn get_msg_count(&self) -> Result<i64, StoreError> {
-        let query = "SELECT COUNT(*) FROM Messages";
-        Ok(self
-            .conn
+    fn current_user_id(&self) -> String {
+        self.current_user
             .lock()
-            .query_row(query, NO_PARAMS, |row| row.get(0))?)
+            .borrow()
+            .unwrap_or(UserId(0))
+            .0
+            .to_string()
+    }
+
+    pub fn get_msg_count(&self, filter: &Filter) -> Result<i64, StoreError> {
+        let (condition, params) = filter.messages_condition();
+        // language=sql
+        let query = format!("SELECT COUNT(*) FROM Messages WHERE {}", condition);
+
+        Ok(self
+            .readers
+            .get()?
+            .query_row(&query, &params, |row| row.get(0))?)
     }
 
-    pub fn get_user_msg_count(&self) -> Result<i64, StoreError> {
+    pub fn get_user_msg_count(&self, filter: &Filter) -> Result<i64, StoreError> {
+        let (condition, filter_params) = filter.messages_condition();
         // language=sql
-        let query = "SELECT COUNT(*)
+        let query = format!(
+            "SELECT COUNT(*)
         FROM Messages
-        WHERE AuthorId = ?";
+        WHERE AuthorId = ? AND {}",
+            condition
+        );
 
-        let id = self
-            .current_user
-            .lock()
-            .borrow()
-            .unwrap_or(UserId(0))
-            .0
-            .to_string();
+        let mut params = vec![Value::Text(self.current_user_id())];
+        params.extend(filter_params);
 
-        Ok(self.conn.lock().query_row(query, &[id], |row| row.get(0))?)
+        Ok(self
+            .readers
+            .get()?
+            .query_row(&query, &params, |row| row.get(0))?)
     }
 
-    pub fn get_user_msgs_per_day(&self) -> Result<Vec<(String, i64, i64)>, StoreError> {
+    pub fn get_user_msgs_per_day(
+        &self,
+        filter: &Filter,
+    ) -> Result<Vec<DailyMessageCount>, StoreError> {
+        let (condition, filter_params) = filter.messages_condition();
         // language=sql
-        let query = "
+        let query = format!(
+            "
         SELECT DATE('now', '-7 days')   date_limit,
                DATE(Time, 'unixepoch')  msg_date,
                SUM(GuildId IS NOT NULl) msg_count,
                SUM(GuildId ISNULL)      priv_msg_count
         From Messages
-        WHERE AuthorId = ? AND msg_date > date_limit
+        WHERE AuthorId = ? AND msg_date > date_limit AND {}
         GROUP BY msg_date
-        ORDER BY msg_date DESC";
+        ORDER BY msg_date DESC",
+            condition
+        );
 
-        let conn = self.conn.lock();
-        let mut stmt = conn.prepare(query)?;
+        let conn = self.readers.get()?;
+        let mut stmt = conn.prepare(&query)?;
 
-        let id = self
-            .current_user
-            .lock()
-            .borrow()
-            .unwrap_or(UserId(0))
-            .0
-            .to_string();
+        let mut params = vec![Value::Text(self.current_user_id())];
+        params.extend(filter_params);
 
-        stmt.query_map(&[id], |row| Ok((row.get(1)?, row.get(2)?, row.get(3)?)))
-            .map(|rows| rows.flatten().collect::<Vec<_>>())
-            .map_err(Into::into)
+        stmt.query_map(&params, |row| {
+            Ok(DailyMessageCount {
+                date: row.get(1)?,
+                public: row.get(2)?,
+                private: row.get(3)?,
+            })
+        })
+        .map(|rows| rows.flatten().collect::<Vec<_>>())
+        .map_err(Into::into)
     }
 
-    pub fn get_total_msgs_per_day(&self) -> Result<Vec<(String, i64, i64)>, StoreError> {
+    pub fn get_total_msgs_per_day(
+        &self,
+        filter: &Filter,
+    ) -> Result<Vec<DailyMessageCount>, StoreError> {
+        let (condition, params) = filter.messages_condition();
         // language=sql
-        let query = "
+        let query = format!(
+            "
         SELECT DATE(Time, 'unixepoch') msg_date, SUM(GuildId IS NOT NULl) msg_count, SUM(GuildId ISNULL)
         From Messages
+        WHERE {}
         GROUP BY msg_date
-        ORDER BY msg_date ASC";
+        ORDER BY msg_date ASC",
+            condition
+        );
 
-        let conn = self.conn.lock();
-        let mut stmt = conn.prepare(query)?;
+        let conn = self.readers.get()?;
+        let mut stmt = conn.prepare(&query)?;
 
-        stmt.query_map(NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
-            .map(|rows| rows.flatten().collect::<Vec<_>>())
-            .map_err(Into::into)
+        stmt.query_map(&params, |row| {
+            Ok(DailyMessageCount {
+                date: row.get(0)?,
+                public: row.get(1)?,
+                private: row.get(2)?,
+            })
+        })
+        .map(|rows| rows.flatten().collect::<Vec<_>>())
+        .map_err(Into::into)
     }
 
-    pub fn get_edit_count(&self) -> Result<i64, StoreError> {
+    pub fn get_edit_count(&self, filter: &Filter) -> Result<i64, StoreError> {
+        let (condition, params) = filter.related_condition();
         //language=sql
-        let query = "
-        SELECT IFNULL(SUM(json_array_length(EditContents)), 0) FROM Edits";
+        let query = format!(
+            "
+        SELECT IFNULL(SUM(json_array_length(EditContents)), 0) FROM Edits WHERE {}",
+            condition
+        );
 
-        self.conn
-            .lock()
-            .query_row(query, NO_PARAMS, |row| row.get(0))
+        self.readers
+            .get()?
+            .query_row(&query, &params, |row| row.get(0))
             .map_err(Into::into)
     }
 
-    pub fn get_channels(&self) -> Result<Vec<Channel>, StoreError> {
+    pub fn get_deletion_count(&self, filter: &Filter) -> Result<i64, StoreError> {
+        let (condition, params) = filter.related_condition();
         // language=sql
-        let query = "SELECT DISTINCT ChannelId, GuildId FROM Messages";
+        let query = format!("SELECT COUNT(*) FROM Deletions WHERE {}", condition);
 
-        let conn = self.conn.lock();
-        let mut stmt = conn.prepare(query)?;
+        self.readers
+            .get()?
+            .query_row(&query, &params, |row| row.get(0))
+            .map_err(Into::into)
+    }
+
+    pub fn get_channels(&self, filter: &Filter) -> Result<Vec<Channel>, StoreError> {
+        let (condition, params) = filter.messages_condition();
+        // language=sql
+        let query = format!(
+            "SELECT DISTINCT COALESCE(ParentChannelId, ChannelId), GuildId FROM Messages WHERE {}",
+            condition
+        );
+
+        let conn = self.readers.get()?;
+        let mut stmt = conn.prepare(&query)?;
 
         // TODO: figure out error handling here
-        stmt.query_map(NO_PARAMS, |row| {
+        stmt.query_map(&params, |row| {
             Ok(Channel {
                 channel_id: row
                     .get::<_, String>(0)?
@@ -325,14 +965,121 @@ impl StatsStore {
         .map_err(Into::into)
     }
 
-    pub fn get_guilds(&self) -> Result<Vec<GuildId>, StoreError> {
+    /// Channels by number of messages, most first
+    pub fn get_channel_msg_counts(
+        &self,
+        filter: &Filter,
+        limit: usize,
+    ) -> Result<Vec<(Channel, i64)>, StoreError> {
+        let (condition, mut params) = filter.messages_condition();
+        params.push(Value::Integer(limit as i64));
         // language=sql
-        let query = "SELECT DISTINCT GuildId FROM Messages";
+        let query = format!(
+            "
+        SELECT COALESCE(ParentChannelId, ChannelId) channel, GuildId, COUNT(*) msg_count
+        FROM Messages
+        WHERE {}
+        GROUP BY channel, GuildId
+        ORDER BY msg_count DESC, channel
+        LIMIT ?",
+            condition
+        );
+
+        let conn = self.readers.get()?;
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(&params, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })?;
+
+        Ok(rows
+            .flatten()
+            .filter_map(|(channel_id, guild_id, count)| {
+                let channel = Channel {
+                    channel_id: ChannelId(channel_id.parse().ok()?),
+                    guild_id: guild_id.and_then(|id| id.parse().ok()).map(GuildId),
+                };
+                Some((channel, count))
+            })
+            .collect())
+    }
 
-        let conn = self.conn.lock();
-        let mut stmt = conn.prepare(query)?;
+    /// Users the current user shares direct message channels with, by the number of
+    /// messages in those channels
+    ///
+    /// Messages in group channels count for every member.
+    pub fn get_dm_partners(
+        &self,
+        filter: &Filter,
+        limit: usize,
+    ) -> Result<Vec<(UserId, i64)>, StoreError> {
+        let (condition, mut params) = filter.messages_condition();
+        params.push(Value::Text(self.current_user_id()));
+        params.push(Value::Integer(limit as i64));
+        // language=sql
+        let query = format!(
+            "
+        SELECT p.AuthorId, SUM(c.msg_count) total
+        FROM (SELECT ChannelId, COUNT(*) msg_count
+              FROM Messages
+              WHERE GuildId ISNULL AND {}
+              GROUP BY ChannelId) c
+        JOIN (SELECT DISTINCT ChannelId, AuthorId
+              FROM Messages
+              WHERE GuildId ISNULL AND AuthorId != ?) p ON p.ChannelId = c.ChannelId
+        GROUP BY p.AuthorId
+        ORDER BY total DESC, p.AuthorId
+        LIMIT ?",
+            condition
+        );
+
+        let conn = self.readers.get()?;
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(&params, |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?;
+
+        Ok(rows
+            .flatten()
+            .filter_map(|(user_id, count)| Some((UserId(user_id.parse().ok()?), count)))
+            .collect())
+    }
 
-        stmt.query_map(NO_PARAMS, |row| row.get::<_, Option<String>>(0))
+    /// Days the current user sent messages on, as `YYYY-MM-DD` in ascending order
+    pub fn get_user_active_days(&self, filter: &Filter) -> Result<Vec<String>, StoreError> {
+        let (condition, filter_params) = filter.messages_condition();
+        // language=sql
+        let query = format!(
+            "
+        SELECT DISTINCT DATE(Time, 'unixepoch') msg_date
+        FROM Messages
+        WHERE AuthorId = ? AND {}
+        ORDER BY msg_date ASC",
+            condition
+        );
+
+        let mut params = vec![Value::Text(self.current_user_id())];
+        params.extend(filter_params);
+
+        let conn = self.readers.get()?;
+        let mut stmt = conn.prepare(&query)?;
+        stmt.query_map(&params, |row| row.get(0))
+            .map(|rows| rows.flatten().collect::<Vec<_>>())
+            .map_err(Into::into)
+    }
+
+    pub fn get_guilds(&self, filter: &Filter) -> Result<Vec<GuildId>, StoreError> {
+        let (condition, params) = filter.messages_condition();
+        // language=sql
+        let query = format!("SELECT DISTINCT GuildId FROM Messages WHERE {}", condition);
+
+        let conn = self.readers.get()?;
+        let mut stmt = conn.prepare(&query)?;
+
+        stmt.query_map(&params, |row| row.get::<_, Option<String>>(0))
             .map(|rows| {
                 let mut out: Vec<Option<u64>> = Vec::new();
 
@@ -346,8 +1093,340 @@ impl StatsStore {
             })
             .map_err(Into::into)
     }
+
+    /// Domains by the number of links to them, most first
+    pub fn get_link_domains(
+        &self,
+        filter: &Filter,
+        limit: usize,
+    ) -> Result<Vec<DomainCount>, StoreError> {
+        let (condition, mut params) = filter.messages_condition();
+        params.push(Value::Integer(limit as i64));
+        // language=sql
+        let query = format!(
+            "
+        SELECT l.Domain, COUNT(*) link_count
+        FROM Links l
+        JOIN (SELECT MessageId, ChannelId FROM Messages WHERE {}) m
+            ON l.MessageId = m.MessageId AND l.ChannelId = m.ChannelId
+        GROUP BY l.Domain
+        ORDER BY link_count DESC, l.Domain
+        LIMIT ?",
+            condition
+        );
+
+        let conn = self.readers.get()?;
+        let mut stmt = conn.prepare(&query)?;
+        stmt.query_map(&params, |row| {
+            Ok(DomainCount {
+                domain: row.get(0)?,
+                count: row.get(1)?,
+            })
+        })
+        .map(|rows| rows.flatten().collect::<Vec<_>>())
+        .map_err(Into::into)
+    }
+
+    /// Links by the number of messages sharing them, most first
+    pub fn get_top_links(
+        &self,
+        filter: &Filter,
+        limit: usize,
+    ) -> Result<Vec<SharedLink>, StoreError> {
+        let (condition, mut params) = filter.messages_condition();
+        params.push(Value::Integer(limit as i64));
+        // language=sql
+        let query = format!(
+            "
+        SELECT l.Url, l.Domain, COUNT(*) link_count, MIN(m.Time)
+        FROM Links l
+        JOIN (SELECT MessageId, ChannelId, Time FROM Messages WHERE {}) m
+            ON l.MessageId = m.MessageId AND l.ChannelId = m.ChannelId
+        GROUP BY l.Url
+        ORDER BY link_count DESC, l.Url
+        LIMIT ?",
+            condition
+        );
+
+        let conn = self.readers.get()?;
+        let mut stmt = conn.prepare(&query)?;
+        stmt.query_map(&params, |row| {
+            Ok(SharedLink {
+                url: row.get(0)?,
+                domain: row.get(1)?,
+                count: row.get(2)?,
+                first_shared: row.get(3)?,
+            })
+        })
+        .map(|rows| rows.flatten().collect::<Vec<_>>())
+        .map_err(Into::into)
+    }
+
+    /// Links shared per day, oldest first, only days with links are included
+    pub fn get_links_per_day(&self, filter: &Filter) -> Result<Vec<(String, i64)>, StoreError> {
+        let (condition, params) = filter.messages_condition();
+        // language=sql
+        let query = format!(
+            "
+        SELECT DATE(m.Time, 'unixepoch') link_date, COUNT(*)
+        FROM Links l
+        JOIN (SELECT MessageId, ChannelId, Time FROM Messages WHERE {}) m
+            ON l.MessageId = m.MessageId AND l.ChannelId = m.ChannelId
+        GROUP BY link_date
+        ORDER BY link_date ASC",
+            condition
+        );
+
+        let conn = self.readers.get()?;
+        let mut stmt = conn.prepare(&query)?;
+        stmt.query_map(&params, |row| Ok((row.get(0)?, row.get(1)?)))
+            .map(|rows| rows.flatten().collect::<Vec<_>>())
+            .map_err(Into::into)
+    }
+
+    /// Who sent the messages matching the filter and when, in order per channel
+    pub fn get_timeline(&self, filter: &Filter) -> Result<Vec<TimelineMessage>, StoreError> {
+        let (condition, params) = filter.messages_condition();
+        // language=sql
+        let query = format!(
+            "SELECT ChannelId, Time, AuthorId FROM Messages WHERE {} ORDER BY ChannelId, Time, EventId",
+            condition
+        );
+
+        let conn = self.readers.get()?;
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(&params, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+
+        let mut timeline = Vec::new();
+        for row in rows {
+            let (channel_id, time, author_id) = row?;
+            if let (Ok(channel_id), Ok(author_id)) = (channel_id.parse(), author_id.parse()) {
+                timeline.push(TimelineMessage {
+                    channel_id: ChannelId(channel_id),
+                    author_id: UserId(author_id),
+                    time,
+                });
+            }
+        }
+        Ok(timeline)
+    }
+
+    /// Calls `f` with every message matching the filter that has content, in no particular order
+    pub fn for_each_content<F>(&self, filter: &Filter, mut f: F) -> Result<(), StoreError>
+    where
+        F: FnMut(MessageContent),
+    {
+        let (condition, params) = filter.messages_condition();
+        // language=sql
+        let query = format!(
+            "SELECT COALESCE(ParentChannelId, ChannelId), GuildId, AuthorId, Time, Content FROM Messages
+            WHERE Content IS NOT NULL AND Content != '' AND {}",
+            condition
+        );
+
+        let conn = self.readers.get()?;
+        let mut stmt = conn.prepare(&query)?;
+        let mut rows = stmt.query(&params)?;
+        while let Some(row) = rows.next()? {
+            let ids = (
+                row.get::<_, String>(0)?.parse(),
+                row.get::<_, Option<String>>(1)?,
+                row.get::<_, String>(2)?.parse(),
+            );
+            if let (Ok(channel_id), guild_id, Ok(author_id)) = ids {
+                f(MessageContent {
+                    channel_id: ChannelId(channel_id),
+                    guild_id: guild_id.and_then(|id| id.parse().ok()).map(GuildId),
+                    author_id: UserId(author_id),
+                    time: row.get(3)?,
+                    content: row.get(4)?,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Last seen names of the users recorded since names are stored
+    pub fn get_user_names(&self) -> Result<HashMap<UserId, String>, StoreError> {
+        let conn = self.readers.get()?;
+        // language=sql
+        let mut stmt = conn.prepare("SELECT UserId, Name FROM Users")?;
+        let names = stmt
+            .query_map(NO_PARAMS, |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?))
+            })?
+            .flatten()
+            .filter_map(|(user_id, name)| Some((UserId(user_id.parse().ok()?), name?)))
+            .collect();
+        Ok(names)
+    }
+
+    /// Users and how often they mentioned or replied to each other
+    ///
+    /// Replies to messages that are not stored count for the author Discord sent with the reply.
+    pub fn get_interaction_graph(&self, filter: &Filter) -> Result<InteractionGraph, StoreError> {
+        let (condition, params) = filter.messages_condition();
+        let conn = self.readers.get()?;
+        let mut interactions: BTreeMap<(UserId, UserId), Interaction> = BTreeMap::new();
+        let mut messages: BTreeMap<UserId, i64> = BTreeMap::new();
+
+        // language=sql
+        let query = format!(
+            "
+        SELECT m.AuthorId, n.TargetId, COUNT(*)
+        FROM (SELECT MessageId, ChannelId, AuthorId FROM Messages WHERE {}) m
+        JOIN Mentions n ON n.MessageId = m.MessageId AND n.ChannelId = m.ChannelId
+        WHERE n.Kind = 'user' AND n.TargetId != m.AuthorId
+        GROUP BY m.AuthorId, n.TargetId",
+            condition
+        );
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(&params, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (source, target, count) = row?;
+            if let (Ok(source), Ok(target)) = (source.parse(), target.parse()) {
+                interaction(&mut interactions, UserId(source), UserId(target)).mentions += count;
+            }
+        }
+
+        // language=sql
+        let query = format!(
+            "
+        SELECT m.AuthorId, COALESCE(t.AuthorId, m.ReplyToAuthorId) target, COUNT(*)
+        FROM (SELECT ChannelId, AuthorId, ReplyToId, ReplyToAuthorId FROM Messages WHERE {}) m
+        LEFT JOIN Messages t ON t.MessageId = m.ReplyToId AND t.ChannelId = m.ChannelId
+        WHERE m.ReplyToId IS NOT NULL AND target IS NOT NULL AND target != m.AuthorId
+        GROUP BY m.AuthorId, target",
+            condition
+        );
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(&params, |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })?;
+        for row in rows {
+            let (source, target, count) = row?;
+            if let (Ok(source), Ok(target)) = (source.parse(), target.parse()) {
+                interaction(&mut interactions, UserId(source), UserId(target)).replies += count;
+            }
+        }
+
+        // language=sql
+        let query = format!(
+            "SELECT AuthorId, COUNT(*) FROM Messages WHERE {} GROUP BY AuthorId",
+            condition
+        );
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map(&params, |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })?;
+        for row in rows {
+            let (author, count) = row?;
+            if let Ok(author) = author.parse() {
+                messages.insert(UserId(author), count);
+            }
+        }
+        let names = self.get_user_names()?;
+
+        // Mentioned users show up even if none of their messages match the filter
+        for &(source, target) in interactions.keys() {
+            messages.entry(source).or_insert(0);
+            messages.entry(target).or_insert(0);
+        }
+
+        Ok(InteractionGraph {
+            users: messages
+                .into_iter()
+                .map(|(user_id, messages)| GraphUser {
+                    user_id,
+                    name: names.get(&user_id).cloned(),
+                    messages,
+                })
+                .collect(),
+            interactions: interactions.into_iter().map(|(_, i)| i).collect(),
+        })
+    }
+}
+
+fn interaction(
+    interactions: &mut BTreeMap<(UserId, UserId), Interaction>,
+    source: UserId,
+    target: UserId,
+) -> &mut Interaction {
+    interactions
+        .entry((source, target))
+        .or_insert_with(|| Interaction {
+            source,
+            target,
+            mentions: 0,
+            replies: 0,
+        })
+}
+
+/// Ids of channels mentioned as `<#id>` in message content
+fn channel_mentions(content: &str) -> Vec<ChannelId> {
+    content
+        .split("<#")
+        .skip(1)
+        .filter_map(|rest| rest.split('>').next()?.parse().ok())
+        .map(ChannelId)
+        .collect()
 }
 
+/// Schema changes in order, the schema version is the number of applied entries
+///
+/// Only append to this list, databases that already applied an entry never run it again.
+const MIGRATIONS: &[&[&str]] = &[
+    // Initial tables, also created by versions that did not record a schema version
+    &[
+        CREATE_MSGS_TABLE_SQL,
+        CREATE_EDITS_TABLE_SQL,
+        CREATE_DELETIONS_TABLE_SQL,
+        CREATE_META_TABLE_SQL,
+    ],
+    // Progress of interrupted history scans
+    &[CREATE_SCAN_CURSORS_TABLE_SQL],
+    // Indexes for the columns aggregate queries filter on
+    &[
+        "CREATE INDEX IF NOT EXISTS MessagesAuthorTime ON Messages (AuthorId, Time)",
+        "CREATE INDEX IF NOT EXISTS MessagesTime ON Messages (Time)",
+        "CREATE INDEX IF NOT EXISTS MessagesGuildChannel ON Messages (GuildId, ChannelId)",
+        "CREATE INDEX IF NOT EXISTS MessagesChannelTime ON Messages (ChannelId, Time)",
+    ],
+    // Mentions and user names for the interaction graph
+    &[CREATE_MENTIONS_TABLE_SQL, CREATE_USERS_TABLE_SQL],
+    // Links shared in messages, existing messages are added by `reindex`
+    &[
+        CREATE_LINKS_TABLE_SQL,
+        "CREATE INDEX IF NOT EXISTS LinksDomain ON Links (Domain)",
+    ],
+    // The channel of the thread or forum post a message was sent in
+    &[
+        "ALTER TABLE Messages ADD COLUMN ParentChannelId TEXT",
+        "CREATE INDEX IF NOT EXISTS MessagesParentChannel ON Messages (ParentChannelId)",
+    ],
+    // The message a reply answers and its author, filled in by `fetch-history` for older ones
+    &[
+        "ALTER TABLE Messages ADD COLUMN ReplyToId TEXT",
+        "ALTER TABLE Messages ADD COLUMN ReplyToAuthorId TEXT",
+    ],
+];
+
 // language=sql
 const CREATE_MSGS_TABLE_SQL: &str = "CREATE TABLE IF NOT EXISTS Messages
 (
@@ -386,3 +1465,55 @@ CREATE TABLE IF NOT EXISTS Deletions
     UNIQUE (MessageId, ChannelId)
 )
 ";
+
+// language=sql
+const CREATE_META_TABLE_SQL: &str = "
+CREATE TABLE IF NOT EXISTS Meta
+(
+    Key     TEXT PRIMARY KEY,
+    Value   TEXT
+)
+";
+
+// language=sql
+const CREATE_SCAN_CURSORS_TABLE_SQL: &str = "
+CREATE TABLE IF NOT EXISTS ScanCursors
+(
+    ChannelId   TEXT PRIMARY KEY,
+    Before      TEXT,
+    Searched    INTEGER
+)
+";
+
+// language=sql
+const CREATE_MENTIONS_TABLE_SQL: &str = "
+CREATE TABLE IF NOT EXISTS Mentions
+(
+    MessageId   TEXT,
+    ChannelId   TEXT,
+    Kind        TEXT,
+    TargetId    TEXT,
+    UNIQUE (MessageId, ChannelId, Kind, TargetId)
+)
+";
+
+// language=sql
+const CREATE_USERS_TABLE_SQL: &str = "
+CREATE TABLE IF NOT EXISTS Users
+(
+    UserId  TEXT PRIMARY KEY,
+    Name    TEXT
+)
+";
+
+// language=sql
+const CREATE_LINKS_TABLE_SQL: &str = "
+CREATE TABLE IF NOT EXISTS Links
+(
+    MessageId   TEXT,
+    ChannelId   TEXT,
+    Url         TEXT,
+    Domain      TEXT,
+    UNIQUE (MessageId, ChannelId, Url)
+)
+";
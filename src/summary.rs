@@ -0,0 +1,183 @@
+use chrono::{Duration, NaiveDate};
+use console::style;
+use serde_derive::Serialize;
+use std::fmt::Write;
+
+use crate::error::StoreError;
+use crate::store::{Filter, StatsStore};
+
+/// Channels listed in the summary
+const TOP_CHANNELS: usize = 10;
+/// Widest the sparkline gets, longer ranges are summed into buckets of several days
+const SPARKLINE_WIDTH: usize = 60;
+const SPARK_LEVELS: &[char] = &['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The numbers shown on the dashboard, for printing in the terminal
+///
+/// Ids are strings so the JSON output matches the API.
+#[derive(Serialize)]
+pub struct Summary {
+    pub total: i64,
+    pub user: i64,
+    pub edits: i64,
+    pub deletions: i64,
+    pub guilds: usize,
+    pub channels: usize,
+    pub direct_channels: usize,
+    /// Every day from the first to the last message, including days without messages
+    pub per_day: Vec<DayCount>,
+    pub top_channels: Vec<ChannelCount>,
+}
+
+#[derive(Serialize)]
+pub struct DayCount {
+    pub date: String,
+    pub count: i64,
+}
+
+#[derive(Serialize)]
+pub struct ChannelCount {
+    pub channel_id: String,
+    /// Missing for direct messages
+    pub guild_id: Option<String>,
+    pub count: i64,
+}
+
+pub fn build(stats: &StatsStore, filter: &Filter) -> Result<Summary, StoreError> {
+    let channels = stats.get_channels(filter)?;
+    let direct_channels = channels
+        .iter()
+        .filter(|channel| channel.guild_id.is_none())
+        .count();
+
+    let days = stats
+        .get_total_msgs_per_day(filter)?
+        .into_iter()
+        .filter_map(|day| {
+            let date = NaiveDate::parse_from_str(&day.date, "%Y-%m-%d").ok()?;
+            Some((date, day.public + day.private))
+        })
+        .collect::<Vec<_>>();
+
+    Ok(Summary {
+        total: stats.get_msg_count(filter)?,
+        user: stats.get_user_msg_count(filter)?,
+        edits: stats.get_edit_count(filter)?,
+        deletions: stats.get_deletion_count(filter)?,
+        guilds: stats.get_guilds(filter)?.len(),
+        channels: channels.len() - direct_channels,
+        direct_channels,
+        per_day: fill_days(&days),
+        top_channels: stats
+            .get_channel_msg_counts(filter, TOP_CHANNELS)?
+            .into_iter()
+            .map(|(channel, count)| ChannelCount {
+                channel_id: channel.channel_id.0.to_string(),
+                guild_id: channel.guild_id.map(|guild_id| guild_id.0.to_string()),
+                count,
+            })
+            .collect(),
+    })
+}
+
+/// Adds the days without messages between the first and last day
+fn fill_days(days: &[(NaiveDate, i64)]) -> Vec<DayCount> {
+    let (first, last) = match (days.first(), days.last()) {
+        (Some(&(first, _)), Some(&(last, _))) => (first, last),
+        _ => return Vec::new(),
+    };
+
+    let mut filled = Vec::new();
+    let mut counts = days.iter().peekable();
+    let mut date = first;
+    while date <= last {
+        let count = match counts.peek() {
+            Some(&&(day, count)) if day == date => {
+                counts.next();
+                count
+            }
+            _ => 0,
+        };
+        filled.push(DayCount {
+            date: date.to_string(),
+            count,
+        });
+        date = date + Duration::days(1);
+    }
+    filled
+}
+
+/// The summary as aligned tables with a sparkline of messages per day
+pub fn render(summary: &Summary) -> String {
+    let mut out = String::new();
+
+    let totals = [
+        ("Messages", summary.total.to_string()),
+        ("Sent by you", summary.user.to_string()),
+        ("Edits", summary.edits.to_string()),
+        ("Deletions", summary.deletions.to_string()),
+        ("Guilds", summary.guilds.to_string()),
+        ("Channels", summary.channels.to_string()),
+        ("Direct messages", summary.direct_channels.to_string()),
+    ];
+    for (label, value) in &totals {
+        let _ = writeln!(out, "{:<16} {:>10}", label, value);
+    }
+
+    if let (Some(first), Some(last)) = (summary.per_day.first(), summary.per_day.last()) {
+        let counts = summary
+            .per_day
+            .iter()
+            .map(|day| day.count)
+            .collect::<Vec<_>>();
+        let _ = writeln!(
+            out,
+            "\n{} {} to {}, up to {} a day",
+            style("Messages per day").bold(),
+            first.date,
+            last.date,
+            counts.iter().max().unwrap_or(&0)
+        );
+        let _ = writeln!(out, "{}", sparkline(&counts, SPARKLINE_WIDTH));
+    }
+
+    if !summary.top_channels.is_empty() {
+        let _ = writeln!(out, "\n{}", style("Top channels").bold());
+        let _ = writeln!(out, "{:<20} {:<20} {:>10}", "Channel", "Guild", "Messages");
+        for channel in &summary.top_channels {
+            let guild = channel
+                .guild_id
+                .clone()
+                .unwrap_or_else(|| "direct message".to_owned());
+            let _ = writeln!(
+                out,
+                "{:<20} {:<20} {:>10}",
+                channel.channel_id, guild, channel.count
+            );
+        }
+    }
+
+    out
+}
+
+/// Draws the values as block characters, summing neighbours if there are more than `width`
+fn sparkline(values: &[i64], width: usize) -> String {
+    let bucket_size = (values.len() + width - 1) / width;
+    let buckets = values
+        .chunks(bucket_size.max(1))
+        .map(|chunk| chunk.iter().sum::<i64>())
+        .collect::<Vec<_>>();
+    let max = buckets.iter().cloned().max().unwrap_or(0);
+
+    buckets
+        .iter()
+        .map(|&value| {
+            if max == 0 {
+                SPARK_LEVELS[0]
+            } else {
+                let level = value * (SPARK_LEVELS.len() as i64 - 1) / max;
+                SPARK_LEVELS[level as usize]
+            }
+        })
+        .collect()
+}
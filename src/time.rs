@@ -0,0 +1,44 @@
+use chrono::{DateTime, NaiveDate};
+
+/// Which end of a time range a parsed time is used for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Start,
+    End,
+}
+
+/// Parses a unix timestamp, an RFC 3339 date and time or a `YYYY-MM-DD` date
+///
+/// A date covers the whole day, so as an end bound it is the start of the following day.
+pub fn parse_time(s: &str, bound: Bound) -> Option<i64> {
+    if let Ok(timestamp) = s.parse::<i64>() {
+        return Some(timestamp);
+    }
+    if let Ok(date_time) = DateTime::parse_from_rfc3339(s) {
+        return Some(date_time.timestamp());
+    }
+
+    let date = NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?;
+    let date = match bound {
+        Bound::Start => date,
+        Bound::End => date.succ(),
+    };
+    Some(date.and_hms(0, 0, 0).timestamp())
+}
+
+/// Start of 2015 in unix milliseconds, the time Discord's snowflake ids count from
+const DISCORD_EPOCH: i64 = 1_420_070_400_000;
+
+/// The smallest snowflake id Discord can assign at a unix timestamp
+///
+/// Every id created at or after `timestamp` is at least this, every earlier one is lower.
+/// `None` if the timestamp is too far from Discord's epoch to be in an id.
+pub fn snowflake_at(timestamp: i64) -> Option<u64> {
+    let millis = timestamp.checked_mul(1000)?.checked_sub(DISCORD_EPOCH)?;
+    let since_epoch = millis.max(0) as u64;
+    // Ids keep 42 bits for the time, the rest would be shifted out
+    if since_epoch >> 42 != 0 {
+        return None;
+    }
+    Some(since_epoch << 22)
+}
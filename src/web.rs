@@ -0,0 +1,116 @@
+use iron::error::HttpError;
+use iron::modifiers::RedirectRaw;
+use iron::prelude::*;
+use iron::status;
+use iron::Listening;
+use log::info;
+use persistent::Read;
+use router::Router;
+use std::sync::Arc;
+
+use crate::api;
+use crate::auth::Authenticator;
+use crate::config::{AnalysisConfig, WebConfig};
+use crate::events::EventBus;
+use crate::store::StatsStore;
+
+/// Path every API endpoint is served under
+pub const API_PREFIX: &str = "/api/v1";
+
+type Handler = fn(&mut Request) -> IronResult<Response>;
+
+/// Starts the dashboard and API server on background threads
+///
+/// The server runs until `close` is called on the returned listener.
+pub fn serve(
+    config: &WebConfig,
+    analysis: &AnalysisConfig,
+    stats: Arc<StatsStore>,
+    events: Arc<EventBus>,
+) -> Result<Listening, HttpError> {
+    let mut chain = Chain::new(build_router(&config.base_path()));
+    chain.link_before(Authenticator::new(config.auth.clone()));
+    chain.link(Read::<api::Stats>::both(stats));
+    chain.link(Read::<api::Events>::both(events));
+    chain.link(Read::<api::Analysis>::both(analysis.clone()));
+
+    let listening = Iron::new(chain).http((config.bind_address.as_str(), config.port))?;
+    info!(
+        "Web server listening on http://{}{}/",
+        listening.socket,
+        config.base_path()
+    );
+
+    Ok(listening)
+}
+
+fn build_router(base: &str) -> Router {
+    let mut router = Router::new();
+
+    let api_routes: &[(&str, &str, Handler)] = &[
+        (
+            "api_total_msg_count_per_day",
+            "/total_msg_count_per_day",
+            api::total_msg_count_per_day,
+        ),
+        (
+            "api_user_msg_count_per_day",
+            "/user_msg_count_per_day",
+            api::msg_count_per_day,
+        ),
+        (
+            "api_total_msg_count",
+            "/total_msg_count",
+            api::total_msg_count,
+        ),
+        ("api_edit_count", "/edit_count", api::edit_count),
+        ("api_channels", "/channels", api::get_channels),
+        ("api_msg_count", "/msg_count", api::msg_count),
+        ("api_guilds", "/guilds", api::get_guilds),
+        ("api_sessions", "/sessions", api::get_sessions),
+        ("api_words", "/words", api::get_words),
+        ("api_emoji", "/emoji", api::get_emoji),
+        ("api_code", "/code", api::get_code),
+        ("api_link_domains", "/link_domains", api::link_domains),
+        ("api_top_links", "/top_links", api::top_links),
+        ("api_links_per_day", "/links_per_day", api::links_per_day),
+        (
+            "api_response_times",
+            "/response_times",
+            api::get_response_times,
+        ),
+        (
+            "api_interaction_graph",
+            "/interaction_graph",
+            api::interaction_graph,
+        ),
+        ("api_events", "/events", api::events),
+        ("api_status", "/status", api::status),
+        ("api_openapi", "/openapi.json", api::openapi),
+    ];
+    for &(id, path, handler) in api_routes {
+        router.get(format!("{}{}{}", base, API_PREFIX, path), handler, id);
+    }
+
+    let dashboard_routes: &[(&str, &str, Handler)] = &[
+        ("metrics", "/metrics", api::prometheus_metrics),
+        ("dashboard_js", "/index.js", api::dashboard_js),
+        ("dashboard_g", "/*", api::dashboard),
+        ("dashboard", "/", api::dashboard),
+    ];
+    for &(id, path, handler) in dashboard_routes {
+        router.get(format!("{}{}", base, path), handler, id);
+    }
+
+    if !base.is_empty() {
+        // The dashboard uses relative urls, so it must be served from a directory
+        let target = format!("{}/", base);
+        router.get(
+            base,
+            move |_: &mut Request| Ok(Response::with((status::Found, RedirectRaw(target.clone())))),
+            "base_redirect",
+        );
+    }
+
+    router
+}
@@ -1,59 +1,139 @@
-let msg_count = fetch("/api/total_msg_count").then(x => x.json());
-let edit_count = fetch("/api/edit_count").then(x => x.json());
-let user_msg_count = fetch("/api/msg_count").then(x => x.json());
-let channels = fetch("/api/channels").then(x => x.json());
-let guilds = fetch("/api/guilds").then(x => x.json());
-let msgs_per_day = fetch("/api/user_msg_count_per_day").then(x => x.json()).then(x => x.map(([date, ...x]) => [new Date(date), ...x]))
+function fetchJson(path) {
+    return fetch(path).then(x => x.json())
+}
 
-window.onload = function populate() {
+function fetchStats() {
+    let msg_count = fetchJson("api/v1/total_msg_count").then(x => x.count);
+    let edit_count = fetchJson("api/v1/edit_count").then(x => x.count);
+    let user_msg_count = fetchJson("api/v1/msg_count").then(x => x.count);
+    let channels = fetchJson("api/v1/channels");
+    let guilds = fetchJson("api/v1/guilds");
+    let msgs_per_day = fetchJson("api/v1/user_msg_count_per_day").then(x => x.map(day => ({...day, date: new Date(day.date)})))
 
-    Promise.all([msg_count, user_msg_count, channels, guilds, msgs_per_day, edit_count]).then(values => {
-        let [msg_count, user_msg_count, channels, guilds, msgs_per_day, edit_count] = values
-        let stats_textarea = document.getElementById("stats");
-        let channel_count = channels.filter(i => i['guild_id']).length
-        let dm_count = channels.length - channel_count
-
-        stats_textarea.innerText = `Total logged messages: ${msg_count}
-        Users logged messages: ${user_msg_count}
-        Logged guilds: ${guilds.length}
-        Logged channels: ${channel_count}
-        Logged direct message channels: ${dm_count}
-        Total edits: ${edit_count}`
-
-        let x_axis = ["x"]
-        let user_msgs_pub = ["public"]
-        let user_msgs_priv = ["private"]
-        for (let day of msgs_per_day) {
-            x_axis.push(day[0])
-
-            user_msgs_pub.push(day[1])
-            user_msgs_priv.push(day[2])
-        }
+    return Promise.all([msg_count, user_msg_count, channels, guilds, msgs_per_day, edit_count])
+}
 
-        c3.generate({
-            bindto: '#sent-messages',
-            data: {
-                x: 'x',
-                columns: [
-                    x_axis, user_msgs_priv, user_msgs_pub
-                ],
-                type: 'area',
-                names: {
-                    "private": "Private Messages",
-                    "public": "Public Messages"
-                }
-            },
-            axis: {
-                x: {
-                    type: 'timeseries',
-                    tick: {
-                        format: '%Y-%m-%d'
-                    }
+let stats = fetchStats()
+
+function render(values) {
+    let [msg_count, user_msg_count, channels, guilds, msgs_per_day, edit_count] = values
+    let stats_textarea = document.getElementById("stats");
+    let channel_count = channels.filter(i => i['guild_id']).length
+    let dm_count = channels.length - channel_count
+
+    stats_textarea.innerText = `Total logged messages: ${msg_count}
+    Users logged messages: ${user_msg_count}
+    Logged guilds: ${guilds.length}
+    Logged channels: ${channel_count}
+    Logged direct message channels: ${dm_count}
+    Total edits: ${edit_count}`
+
+    let x_axis = ["x"]
+    let user_msgs_pub = ["public"]
+    let user_msgs_priv = ["private"]
+    for (let day of msgs_per_day) {
+        x_axis.push(day.date)
+
+        user_msgs_pub.push(day.public)
+        user_msgs_priv.push(day.private)
+    }
+
+    c3.generate({
+        bindto: '#sent-messages',
+        data: {
+            x: 'x',
+            columns: [
+                x_axis, user_msgs_priv, user_msgs_pub
+            ],
+            type: 'area',
+            names: {
+                "private": "Private Messages",
+                "public": "Public Messages"
+            }
+        },
+        axis: {
+            x: {
+                type: 'timeseries',
+                tick: {
+                    format: '%Y-%m-%d'
                 }
-            },
-            title: {
-                text: "Messages sent"
             }
-        })
+        },
+        title: {
+            text: "Messages sent"
+        }
     })
 }
+
+// Word and code statistics read every message, so they are only loaded once
+function renderWordCloud(stats) {
+    let cloud = document.getElementById("word-cloud")
+    cloud.innerHTML = ""
+    if (stats.words.length === 0) {
+        cloud.innerText = "No words logged yet"
+        return
+    }
+
+    let max = stats.words[0].count
+    let min = stats.words[stats.words.length - 1].count
+    for (let word of stats.words) {
+        let weight = max === min ? 1 : (word.count - min) / (max - min)
+        let span = document.createElement("span")
+        span.innerText = word.text
+        span.title = `${word.count} times`
+        span.style.fontSize = `${0.8 + weight * 2.2}em`
+        span.style.marginRight = "0.5em"
+        span.style.display = "inline-block"
+        cloud.appendChild(span)
+    }
+}
+
+function renderCode(stats) {
+    let languages = ["lines"]
+    let names = []
+    for (let language of stats.languages) {
+        names.push(language.language || "plain text")
+        languages.push(language.lines)
+    }
+
+    c3.generate({
+        bindto: '#code-languages',
+        data: {
+            columns: [languages],
+            type: 'bar',
+            names: {
+                "lines": "Lines of code"
+            }
+        },
+        axis: {
+            x: {
+                type: 'category',
+                categories: names
+            }
+        },
+        title: {
+            text: `Code shared: ${stats.lines} lines in ${stats.blocks} blocks`
+        }
+    })
+}
+
+// Refresh when the collector records changes, at most once a second
+function listen() {
+    let pending = null
+    let events = new EventSource("api/v1/events")
+    events.addEventListener("counters", () => {
+        if (pending === null) {
+            pending = setTimeout(() => {
+                pending = null
+                fetchStats().then(render)
+            }, 1000)
+        }
+    })
+}
+
+window.onload = function populate() {
+    stats.then(render)
+    fetchJson("api/v1/words").then(renderWordCloud)
+    fetchJson("api/v1/code").then(renderCode)
+    listen()
+}